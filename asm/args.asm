@@ -0,0 +1,18 @@
+; Prints the first argument and the value of NAME
+; Run with `machine --env NAME=value args.proj -- hello`
+ADD R8 0x80 $0
+ADD R9 $64 $0
+ADD R10 $1 $0
+SYSCALL ARGS R4 R5 R6
+; R7 = length of argument 1
+SYSCALL ARG R7 R10 R8 R9
+SYSCALL WRITE $0 R11 R10 R8 R7
+; Variable name into memory, then its value
+ADD R12 0xF0 $0
+ADD R13 $0 $0
+ADD R14 $5 $0
+DATACPY R12 R13 R14
+SYSCALL ENV R7 R12 R8 R9
+SYSCALL WRITE $0 R11 R10 R8 R7
+HALT
+[[DATA]]NAME
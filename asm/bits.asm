@@ -0,0 +1,17 @@
+; A = 0x00F0
+ADD A $240 $0
+; B = 0x00F0 & 0x3C = 0x0030
+AND B A 0x3C
+; C = 0x00F0 | 0x0030 = 0x00F0
+ORR C A B
+; B = 0x0030 << 4 = 0x0300
+SHL B B $4
+; M = !0x0300 = 0xFCFF
+NOT M B
+; M = 0xFCFF >> 8 (arithmetic) = 0xFFFC
+SAR M M $8
+; Clear bit 2 of M: 0xFFF8, Z is cleared since the bit was set
+BCLR M $2
+; Rotate A right by 4: 0x000F
+ROR A A $4
+HALT
@@ -0,0 +1,10 @@
+; Times a 250 ms sleep, the elapsed milliseconds end up in R6:R7
+; Run with `machine --virtual-clock` for the same result every time
+SYSCALL TIME R8 R9
+SYSCALL MONOTONIC R4 R5
+ADD R10 $250 $0
+SYSCALL SLEEP R10
+SYSCALL MONOTONIC R6 R7
+SUBR R7 R7 R5
+SBBR R6 R6 R4
+HALT
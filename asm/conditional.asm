@@ -0,0 +1,23 @@
+; Conditional assembly and repetitions
+; Run with `preprocessor -D DEBUG asm/conditional.asm` to print the debug marker
+; and `-D STARS=5` to change how many stars are printed
+!ifndef STARS
+.equ STARS 3
+!endif
+ADD R15 '*' $0
+!rept STARS
+SYSCALL PRINT_CHAR R15
+!endr
+ADD R15 '\n' $0
+SYSCALL PRINT_CHAR R15
+!ifdef DEBUG
+ADD R15 'D' $0
+!elif STARS > 4
+ADD R15 'L' $0
+!else
+ADD R15 'S' $0
+!endif
+SYSCALL PRINT_CHAR R15
+ADD R15 '\n' $0
+SYSCALL PRINT_CHAR R15
+HALT
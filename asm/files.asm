@@ -0,0 +1,42 @@
+; Writes the greeting to report.txt, reads it back to stdout, then lists the root
+; Run with `machine --fs-root dir`
+; Path, greeting and directory name into memory, past the end of the program
+ADD R8 0xA0 $0
+ADD R9 $0 $0
+ADD R10 $11 $0
+DATACPY R8 R9 R10
+ADD R11 0xB0 $0
+ADD R9 $11 $0
+ADD R12 $6 $0
+DATACPY R11 R9 R12
+ADD R12 $5 $0
+ADD R13 0xB8 $0
+ADD R9 $17 $0
+ADD R10 $2 $0
+DATACPY R13 R9 R10
+; fd = OPEN("report.txt", truncate)
+ADD R5 $1 $0
+SYSCALL OPEN R4 R8 R5
+SYSCALL WRITE $0 R6 R4 R11 R12
+SYSCALL CLOSE R6 R4
+; Read it back to stdout
+ADD R5 $0 $0
+SYSCALL OPEN R4 R8 R5
+ADD R14 0xD0 $0
+ADD R15 $32 $0
+SYSCALL READ R7 R4 R14 R15
+ADD R5 $1 $0
+SYSCALL WRITE $0 R6 R5 R14 R7
+SYSCALL CLOSE R6 R4
+; Size of the file, as kind, high and low words at 0xC0
+ADD R14 0xC0 $0
+SYSCALL STAT R6 R8 R14
+; First entry of the root
+ADD R5 $0 $0
+SYSCALL OPEN R4 R13 R5
+ADD R14 0xD0 $0
+SYSCALL READDIR R7 R4 R14 R15
+HALT
+[[DATA]]report.txt
+Hello
+.
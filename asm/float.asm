@@ -0,0 +1,17 @@
+; F0 = 2.25 from the data section, F1 = 1.5 from a literal
+FLD F0 $0
+FLD F1 $1.5
+; F2 = sqrt(2.25) + 1.5 = 3
+FSQRT F2 F0
+FADD F2 F2 F1
+; F3 = -7 as a float, F3 = -7 / 3
+IMUL A $-7 $1
+ITOF F3 A
+FDIV F3 F3 F2
+; B = trunc(-2.333) = -2
+FTOI B F3
+; F3 < F2, sets C
+FCMP F3 F2
+HALT
+
+[[DATA]] .f64 2.25
@@ -0,0 +1,14 @@
+; A = ALLOC(10), B = ALLOC(4)
+ADD C $10 $0
+SYSCALL ALLOC A C
+ADD C $4 $0
+SYSCALL ALLOC B C
+; Fill A with 'x', then grow it to 20 bytes, keeping its contents
+ADD M 0x78 $0
+ADD C $10 $0
+MEMSET A M C
+ADD C $20 $0
+SYSCALL REALLOC A A C
+; B is freed, A is leaked
+SYSCALL FREE B
+HALT
@@ -0,0 +1,8 @@
+; Includes are searched next to this file, then in every `-I` directory
+; Run with `preprocessor -I asm/lib asm/include.asm`
+ADD R4 $42 $0
+SYSCALL PRINT_INT $0 R4
+!include "newline.asm"
+; Every file is only included once, this does nothing
+!include "newline.asm"
+HALT
@@ -0,0 +1,26 @@
+; Reads a name and two numbers, then greets the name with their sum
+; Run with `machine --stdin input.txt` or type the three lines
+ADD R8 0x80 $0
+ADD R9 $32 $0
+SYSCALL READ_LINE R4 R8 R9
+SYSCALL READ_INT R5
+SYSCALL READ_INT R6
+ADDR R7 R5 R6
+; "Hello," from data, then a space
+ADD R10 0x60 $0
+ADD R11 $0 $0
+ADD R12 $7 $0
+DATACPY R10 R11 R12
+SYSCALL PRINT_STR R10
+ADD R13 $32 $0
+SYSCALL PRINT_CHAR R13
+SYSCALL PRINT_STR R8
+ADD R13 $58 $0
+SYSCALL PRINT_CHAR R13
+ADD R13 $32 $0
+SYSCALL PRINT_CHAR R13
+SYSCALL PRINT_INT $1 R7
+ADD R13 $10 $0
+SYSCALL PRINT_CHAR R13
+HALT
+[[DATA]]Hello,
@@ -0,0 +1,3 @@
+; Prints a newline, clobbers R15
+ADD R15 $10 $0
+SYSCALL PRINT_CHAR R15
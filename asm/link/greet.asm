@@ -0,0 +1,6 @@
+; Exports `greet`, its address is relocated by the linker
+!global greet
+ADD R1 $1 $0
+greet:
+ADD R2 $2 $0
+HALT
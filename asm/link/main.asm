@@ -0,0 +1,11 @@
+; Assembled on its own and linked with greet.asm
+; preprocessor -c asm/link/main.asm > main.obj
+; preprocessor -c asm/link/greet.asm > greet.obj
+; linker -o link.proj main.obj greet.obj
+!extern greet
+; Address of `greet`, only known once linked
+ADD R4 greet $0
+SYSCALL PRINT_INT $0 R4
+ADD R15 $10 $0
+SYSCALL PRINT_CHAR R15
+HALT
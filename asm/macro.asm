@@ -1,5 +1,6 @@
-!define_macro FOO => 0x71
-!define_macro BAR => 0x09
+.equ FOO 0x71
+.equ BAR 0x09
 
 ; ADD 0x71, 0x09 into reg A
-; ADD A !FOO !BAR
\ No newline at end of file
+ADD A FOO BAR
+HALT
@@ -0,0 +1,19 @@
+; Copy "Hello" from data to memory at 0x100
+ADD A $0 $0
+ADD B $1 $0
+SHL B B $8
+ADD C $5 $0
+DATACPY B A C
+; Overlapping copy, shift it one byte up: "HHello"
+ADD M $1 $0
+ADDR M M B
+MEMCPY M B C
+; Fill the first byte with '>', compare the two copies
+ADD A 0x3E $0
+ADD C $1 $0
+MEMSET B A C
+ADD C $6 $0
+MEMCMP B M C
+HALT
+
+[[DATA]] Hello
@@ -0,0 +1,23 @@
+; A record with a string and an array in its fields
+NEWREC R4 $2
+NEWSTR R5 $0
+ADD R6 $3 $0
+NEWARR R7 R6
+ADD R8 $0 $0
+SETF R4 R8 R5
+ADD R8 $1 $0
+SETF R4 R8 R7
+; Only the record stays in a register, its fields keep the others alive
+ADD R5 $0 $0
+ADD R7 $0 $0
+NEWSTR R9 $0
+ADD R9 $0 $0
+GC
+; Second byte of the string, through the record
+ADD R8 $0 $0
+GETF R5 R4 R8
+ADD R8 $1 $0
+GETF M R5 R8
+OLEN C R5
+HALT
+[[DATA]]Nova
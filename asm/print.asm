@@ -1,7 +1,23 @@
-; Write to standard out from DATA_SECTION_START+0..DATA_SECTION_START+20
-SYSCALL WRITE $1 $0 $20
-; Write to standard out from DATA_SECTION_START+22..DATA_SECTION_START+59
-SYSCALL WRITE $1 $22 $59
-
-[[DATA]] "This is a print line!\n"
-[[DATA]] "Let's try some escaped \"characters\"\n"
+; Prints -12 in every PRINT_INT mode, one per line
+SUB R4 $0 $12
+ADD R5 $10 $0
+SYSCALL PRINT_INT $0 R4
+SYSCALL PRINT_CHAR R5
+SYSCALL PRINT_INT $1 R4
+SYSCALL PRINT_CHAR R5
+SYSCALL PRINT_INT $2 R4
+SYSCALL PRINT_CHAR R5
+SYSCALL PRINT_INT $3 R4
+SYSCALL PRINT_CHAR R5
+; Copies the greeting into memory and prints it
+.equ BUFFER 0x80
+ADD R6 BUFFER $0
+ADD R7 greeting $0
+ADD R8 sizeof(greeting) $0
+DATACPY R6 R7 R8
+SYSCALL PRINT_STR R6
+SYSCALL PRINT_CHAR R5
+HALT
+[[DATA]]
+.label greeting
+Hello!
@@ -0,0 +1,11 @@
+; Rolls a six-sided die into R5, and fills 8 bytes at 0x80 with noise
+; Run with `machine --seed 42` for the same rolls every time
+SYSCALL RANDOM R4
+ADD R6 $6 $0
+MODR R5 R4 R6
+ADD R10 $1 $0
+ADDR R5 R5 R10
+ADD R8 0x80 $0
+ADD R9 $8 $0
+SYSCALL RANDOM_BYTES R8 R9
+HALT
@@ -0,0 +1,15 @@
+; A = -5 * 3 = -15
+IMUL A $-5 $3
+; B = -15 / 4 = -3, rounded towards zero
+ADD C $4 $0
+IDIVR B A C
+; C = -15 % 4 = -3
+IMODR C A C
+; M = -(-3) = 3
+NEG M B
+; -3 < 3 signed, sets C
+ICMPR B M
+; A = 0xF1 sign extended = 0xFFF1
+ZEXT A A
+SEXT A A
+HALT
@@ -0,0 +1,14 @@
+; C:M = 0x00FF:0xFFFF, built from bytes
+ADD C $255 $0
+ADD M $255 $0
+SHL M M $8
+OR M M 0xFF
+; A:B = 0x0000:0x0001
+ADD A $0 $0
+ADD B $1 $0
+; A:B += C:M, the carry moves into the high word: 0x0100:0x0000
+ADDR B B M
+ADCR A A C
+; A:B = 0xFFFF * 0xFFFF = 0xFFFE:0x0001
+MULW M M
+HALT
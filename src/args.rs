@@ -0,0 +1,208 @@
+use crate::heap::Heap;
+use crate::{Machine, Register};
+
+/// Command-line arguments and environment variables, laid out at the top of memory
+/// - `argc`: word
+/// - `argv`: `argc` pointers to null terminated arguments, then 0
+/// - `envp`: pointers to null terminated `NAME=VALUE` strings, then 0
+/// - The strings themselves, up to the end of memory
+///
+/// Every word is little endian, the heap ends where the region starts
+///
+/// Syscalls, every operand is a register
+/// - `ARGS argc argv envp`: count and table addresses
+/// - `ARG dst i buf len`   : copy of argument `i`
+/// - `ENV dst name buf len`: copy of the value of a variable
+impl Machine {
+    /// Lays out `args` and `env` at the top of memory, `args[0]` is the program name
+    pub fn set_args(&mut self, args: &[String], env: &[String]) -> Result<(), String> {
+        let strings: Vec<&[u8]> = args.iter().chain(env).map(|s| s.as_bytes()).collect();
+        let table = 2 * (1 + args.len() + 1 + env.len() + 1);
+        let size = table + strings.iter().map(|s| s.len() + 1).sum::<usize>();
+        let start = Self::MEMORY_LENGTH
+            .checked_sub(size)
+            .filter(|&start| start >= Heap::START)
+            .ok_or(format!("arguments need {} bytes, too many to fit", size))?
+            & !1;
+
+        let mut words = vec![args.len() as u16];
+        let mut bytes = Vec::new();
+        for (i, s) in strings.iter().enumerate() {
+            if i == args.len() {
+                words.push(0);
+            }
+            words.push((start + table + bytes.len()) as u16);
+            bytes.extend_from_slice(s);
+            bytes.push(0);
+        }
+        if env.is_empty() {
+            words.push(0);
+        }
+        words.push(0);
+
+        let mut region: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        region.extend_from_slice(&bytes);
+        region.resize(Self::MEMORY_LENGTH - start, 0);
+        self.memory[start..].copy_from_slice(&region);
+        self.heap.set_end(start);
+        Ok(())
+    }
+
+    fn word(&self, address: usize) -> u16 {
+        u16::from_le_bytes([self.memory[address], self.memory[address + 1]])
+    }
+
+    /// Argument count, and addresses of the `argv` and `envp` tables, all 0 without arguments
+    fn arg_tables(&self) -> (u16, usize, usize) {
+        let start = self.heap.end();
+        if start >= Self::MEMORY_LENGTH {
+            return (0, 0, 0);
+        }
+        let count = self.word(start);
+        let args = start + 2;
+        (count, args, args + 2 * (count as usize + 1))
+    }
+
+    /// Null terminated strings pointed to by the table at `table`
+    /// The guest can overwrite the table, a pointer outside of memory is an error
+    fn table_strings(&self, table: usize) -> Result<Vec<&[u8]>, String> {
+        let mut strings = Vec::new();
+        if table == 0 {
+            return Ok(strings);
+        }
+        let mut entry = table;
+        while entry + 1 < Self::MEMORY_LENGTH && self.word(entry) != 0 {
+            let ptr = self.word(entry);
+            let s = self
+                .memory
+                .get(ptr as usize..)
+                .filter(|s| !s.is_empty())
+                .ok_or(format!("pointer 0x{:04X} out of bounds", ptr))?;
+            strings.push(&s[..s.iter().position(|&b| b == 0).unwrap_or(s.len())]);
+            entry += 2;
+        }
+        Ok(strings)
+    }
+
+    /// Copies `value` null terminated into `len` bytes at `buf`, returning its length
+    fn copy_string(
+        &mut self,
+        value: Option<Vec<u8>>,
+        buf: usize,
+        len: usize,
+    ) -> Result<u16, String> {
+        let Some(mut value) = value else {
+            return Ok(0xFFFF);
+        };
+        let n = value.len();
+        if n + 1 > len {
+            return Err(format!("{} bytes do not fit in {}", n + 1, len));
+        }
+        value.push(0);
+        self.write_memory(buf, &value)?;
+        Ok(n as u16)
+    }
+
+    /// `ARGS argc argv envp`: every operand is a destination register
+    /// Without arguments all three are 0
+    pub(crate) fn syscall_args(&mut self) -> Result<(), String> {
+        let argc: Register = self.fetch()?;
+        let argv: Register = self.fetch()?;
+        let envp: Register = self.fetch()?;
+
+        let (count, args, env) = self.arg_tables();
+        let (args, env) = (args as u16, env as u16);
+        self.write_register(argc, count);
+        self.write_register(argv, args);
+        self.write_register(envp, env);
+        if self.debug {
+            println!(
+                "| ARGS: {} arguments at 0x{:04X}, environment at 0x{:04X}",
+                count, args, env
+            );
+        }
+        Ok(())
+    }
+
+    /// `ARG dst i buf len`: copies argument `i` into `buf`, `dst` is its length, 0xFFFF past the last one
+    pub(crate) fn syscall_arg(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [i, buf, len] = self.fetch_values()?;
+        let (_, args, _) = self.arg_tables();
+        let value = self
+            .table_strings(args)
+            .map_err(|e| format!("ARG: {}", e))?
+            .get(i as usize)
+            .map(|s| s.to_vec());
+        let n = self.copy_string(value, buf as usize, len as usize)?;
+        self.write_register(dst, n);
+        if self.debug {
+            println!("| ARG: {} -> {}", i, n);
+        }
+        Ok(())
+    }
+
+    /// `ENV dst name buf len`: copies the value of the null terminated `name` into `buf`,
+    /// `dst` is its length, 0xFFFF when it is not set
+    pub(crate) fn syscall_env(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [name, buf, len] = self.fetch_values()?;
+        let mut prefix = self.read_string(name as usize)?.into_bytes();
+        prefix.push(b'=');
+        let (_, _, env) = self.arg_tables();
+        let value = self
+            .table_strings(env)
+            .map_err(|e| format!("ENV: {}", e))?
+            .iter()
+            .find_map(|s| s.strip_prefix(prefix.as_slice()))
+            .map(|s| s.to_vec());
+        let n = self.copy_string(value, buf as usize, len as usize)?;
+        self.write_register(dst, n);
+        if self.debug {
+            println!("| ENV: {} -> {}", String::from_utf8_lossy(&prefix), n);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcode::OpCode;
+    use crate::syscall::Syscall;
+
+    /// Machine with `prog a` as arguments and `HOME=/` in its environment
+    fn machine(syscall: Syscall) -> Machine {
+        let mut m = Machine::new();
+        let args = ["prog".to_string(), "a".to_string()];
+        m.set_args(&args, &["HOME=/".to_string()]).unwrap();
+        // SYSCALL op R0 R1 R2 R3
+        m.set_memory(&[OpCode::SYSCALL as u8, syscall as u8, 0, 1, 2, 3]);
+        m.registers[Register::R2 as usize] = 0x100;
+        m.registers[Register::R3 as usize] = 16;
+        m
+    }
+
+    #[test]
+    fn arg() {
+        let mut m = machine(Syscall::ARG);
+        m.registers[Register::R1 as usize] = 1;
+        m.step().unwrap();
+        assert_eq!(m.register(Register::R0), 1);
+        assert_eq!(&m.memory[0x100..0x102], b"a\0");
+    }
+
+    #[test]
+    fn overwritten_pointer() {
+        let mut m = machine(Syscall::ARG);
+        let (_, argv, env) = m.arg_tables();
+        m.memory[argv..argv + 2].copy_from_slice(&0x1000u16.to_le_bytes());
+        assert_eq!(m.step().unwrap_err(), "ARG: pointer 0x1000 out of bounds");
+
+        let mut m = machine(Syscall::ENV);
+        m.memory[0x80..0x85].copy_from_slice(b"HOME\0");
+        m.registers[Register::R1 as usize] = 0x80;
+        m.memory[env..env + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert_eq!(m.step().unwrap_err(), "ENV: pointer 0xFFFF out of bounds");
+    }
+}
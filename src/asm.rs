@@ -0,0 +1,352 @@
+mod expr;
+mod lexer;
+mod literal;
+mod pp;
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::lines::SourceLine;
+use crate::object::Object;
+use crate::program::Program;
+use pp::PreProcessor;
+
+/// How to assemble a source
+/// - File         : Name of the source, for diagnostics and line tables
+///   Its directory is the first one searched by `!include`
+/// - Include paths: Directories searched by `!include` next
+/// - Defines      : `(NAME, value)` constants, as if the source started with `.equ NAME value`
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub file: String,
+    pub include_paths: Vec<PathBuf>,
+    pub defines: Vec<(String, String)>,
+}
+
+/// An assembly error
+/// - Source       : Line it is about, `None` for errors about the whole source or the options
+/// - Included from: Where the file of that line was included from, innermost first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub source: Option<SourceLine>,
+    pub included_from: Vec<SourceLine>,
+    pub message: String,
+}
+
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Self {
+            source: None,
+            included_from: Vec::new(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(source) = &self.source {
+            write!(f, "{}: ", source)?;
+        }
+        write!(f, "{}", self.message)?;
+        for source in &self.included_from {
+            write!(f, "\n  included from {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every error of a failed assembly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self(vec![diagnostic])
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, diagnostic) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+/// Assembles `source` into a program, ready to load
+pub fn assemble(source: &str, options: &Options) -> Result<Program, Diagnostics> {
+    let mut pp = preprocessor(source, options)?;
+    pp.parse()?;
+    Ok(pp.program())
+}
+
+/// Assembles `source` into a relocatable object, for the linker
+/// `!extern` symbols are only allowed here
+pub fn assemble_object(source: &str, options: &Options) -> Result<Object, Diagnostics> {
+    let mut pp = preprocessor(source, options)?;
+    pp.object_mode();
+    pp.parse()?;
+    Ok(pp.into_object())
+}
+
+fn preprocessor(source: &str, options: &Options) -> Result<PreProcessor, Diagnostic> {
+    let mut pp = PreProcessor::new(&options.file, source.lines().map(String::from).collect());
+    for dir in &options.include_paths {
+        pp.include_path(dir);
+    }
+    for (name, value) in &options.defines {
+        pp.define(name, value)?;
+    }
+    Ok(pp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn code(source: &str) -> Vec<u8> {
+        let options = Options {
+            file: "test.asm".to_string(),
+            ..Options::default()
+        };
+        assemble(source, &options).unwrap().memory
+    }
+
+    fn error(source: &str) -> String {
+        let options = Options {
+            file: "test.asm".to_string(),
+            ..Options::default()
+        };
+        assemble(source, &options).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn inline_comments() {
+        assert_eq!(
+            code("ADD A $1 $2 ; A = 3\nHALT ; done"),
+            code("ADD A $1 $2\nHALT")
+        );
+        // `;` in a character is not a comment
+        assert_eq!(code("ADD A ';' $0"), code("ADD A $59 $0"));
+    }
+
+    #[test]
+    fn block_comments() {
+        let source = "ADD A $1 $2\n;; first\n  HALT\n  still comment ;; ADD B $3 $4\nHALT";
+        assert_eq!(code(source), code("ADD A $1 $2\nADD B $3 $4\nHALT"));
+        assert_eq!(code("ADD A ;; inline ;; $1 $2"), code("ADD A $1 $2"));
+        assert_eq!(
+            error("HALT\n;; never closed\nHALT"),
+            "test.asm:2: unterminated block comment"
+        );
+    }
+
+    #[test]
+    fn whitespace_and_commas() {
+        let expected = code("ADD A $1 $2\nHALT");
+        assert_eq!(code("ADD\tA,\t$1,$2\n\tHALT"), expected);
+        assert_eq!(code("  ADD   A ,  $1 ,  $2  \nHALT"), expected);
+        assert_eq!(code("ADD A,' ',$2"), code("ADD A $32 $2"));
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(code("ADD A ((1 + 2) * 2) $0")[2], 6);
+        assert_eq!(code("ADD A ')' $0"), code("ADD A $41 $0"));
+        assert_eq!(
+            error("HALT\nADD A (1 + 2)) $0"),
+            "test.asm:2: unmatched `)`"
+        );
+        assert_eq!(error("ADD A ((1 + 2) $0"), "test.asm:1: unclosed `(`");
+        assert_eq!(error("ADD A 1) $0"), "test.asm:1: unmatched `)`");
+    }
+
+    #[test]
+    fn data_comments() {
+        let program = assemble(
+            "HALT\n[[DATA]]\n; not data\n Hi ; data",
+            &Options::default(),
+        );
+        assert_eq!(program.unwrap().data, b"\0Hi ; data\0");
+    }
+
+    #[test]
+    fn unknown_words() {
+        assert_eq!(error("ADDD A $1 $2"), "test.asm:1: unknown word `ADDD`");
+        assert_eq!(error("ADD A $1 $2 foo"), "test.asm:1: unknown word `foo`");
+    }
+
+    #[test]
+    fn label_range() {
+        let far = "ADD A far $0\n!rept 100\nADD A $1 $2\n!endr\nfar:\nHALT";
+        assert_eq!(
+            error(far),
+            "test.asm:1: `far` is 404, it does not fit in an 8-bit operand"
+        );
+        assert_eq!(
+            error(&far.replace("far $0", "(far + 0) $0")),
+            "test.asm:1: `(far + 0)` is 404, it does not fit in an 8-bit operand"
+        );
+        assert_eq!(code("ADD A end $0\nend:\nHALT")[2], 4);
+    }
+
+    #[test]
+    fn negative_operands() {
+        // Signed immediates are sign-extended, unsigned ones zero-extended
+        let program = assemble("IMUL A $-5 $3\nADD B $251 $0\nHALT", &Options::default());
+        let mut m = crate::Machine::new();
+        m.load(&program.unwrap());
+        m.step().unwrap();
+        m.step().unwrap();
+        assert_eq!(m.register(crate::Register::A), -15i16 as u16);
+        assert_eq!(m.register(crate::Register::B), 251);
+        assert_eq!(code("IMUL A $-5 $-1")[2..], [0xFB, 0xFF]);
+        assert_eq!(code("ICMP A (1 - 2)")[2], 0xFF);
+        assert_eq!(code("ADD A $255 $0")[2], 0xFF);
+        assert_eq!(
+            error("ADD A $-1 $0"),
+            "test.asm:1: `$-1` is -1, only the immediates of IMUL, IDIV, IMOD and ICMP can be negative"
+        );
+        assert_eq!(
+            error("start:\nHALT\nend:\nADD A (start - end) $0"),
+            "test.asm:4: `(start - end)` is -1, only the immediates of IMUL, IDIV, IMOD and ICMP can be negative"
+        );
+        assert_eq!(
+            error("IMUL A $200 $1"),
+            "test.asm:1: `$200` is 200, it does not fit in a signed 8-bit operand"
+        );
+        assert_eq!(
+            error("IMULR A $-1 B"),
+            "test.asm:1: `$-1` is -1, only the immediates of IMUL, IDIV, IMOD and ICMP can be negative"
+        );
+    }
+
+    #[test]
+    fn reserved_names() {
+        assert_eq!(
+            error("B:\nADD B $1 $2"),
+            "test.asm:1: `B` is a register, it cannot be redefined"
+        );
+        assert_eq!(
+            error("HALT\nADD:"),
+            "test.asm:2: `ADD` is an instruction, it cannot be redefined"
+        );
+        assert_eq!(
+            error("HALT\n[[DATA]]\n.label PRINT_STR\nHi"),
+            "test.asm:3: `PRINT_STR` is a syscall, it cannot be redefined"
+        );
+        assert_eq!(
+            error(".equ F1 2\nHALT"),
+            "test.asm:1: `F1` is a register, it cannot be redefined"
+        );
+        // Names are case sensitive, `b` is not the register
+        assert_eq!(code("b:\nADD B $1 $2\nADD A b $0")[1], 1);
+    }
+
+    #[test]
+    fn sizeof_last_data_label() {
+        let source = "ADD A sizeof(msg) $0\nADD B $1.5 $0\nHALT\n[[DATA]]\n.label msg\nHi";
+        assert_eq!(code(source)[2], 3);
+    }
+
+    #[test]
+    fn data_labels() {
+        let program = assemble(
+            "ADD A text $0\nADD B sizeof(error) $0\nHALT\n[[DATA]]\nHi\n.label text\nError:\n.label error\nError: 2",
+            &Options::default(),
+        )
+        .unwrap();
+        // The `[[DATA]]` line is an empty string of its own
+        assert_eq!(program.data, b"\0Hi\0Error:\0Error: 2\0");
+        assert_eq!(program.memory[2], 4);
+        assert_eq!(program.memory[6], 9);
+        assert_eq!(
+            error("HALT\n[[DATA]]\n.label 2x"),
+            "test.asm:3: `2x` is not a valid name"
+        );
+    }
+
+    #[test]
+    fn label_arithmetic() {
+        let source = "start:\nADD A (end - start) $0\nADD B $1 $2\nend:\nHALT";
+        assert_eq!(code(source)[2], 8);
+        assert_eq!(
+            error(&source.replace("(end - start)", "end - start")),
+            "test.asm:2: `-` is an operator, an expression with spaces goes in parentheses, `(end - start)`"
+        );
+        assert_eq!(code(&source.replace("(end - start)", "end-start"))[2], 8);
+    }
+
+    #[test]
+    fn duplicate_labels() {
+        assert_eq!(
+            error("start:\nHALT\nstart:\nHALT"),
+            "test.asm:3: label `start` is defined twice"
+        );
+        assert_eq!(
+            error("HALT\n[[DATA]]\n.label text\nHi\n.label text\nHo"),
+            "test.asm:5: label `text` is defined twice"
+        );
+    }
+
+    #[test]
+    fn repetitions() {
+        assert_eq!(code("!rept 2\nHALT\n!endr"), code("HALT\nHALT"));
+        assert_eq!(code("!rept 4096\n!endr\nHALT"), code("HALT"));
+        assert_eq!(
+            error("!rept 4097\nHALT\n!endr"),
+            "test.asm:1: `!rept` count 4097 is more than 4096"
+        );
+        assert_eq!(
+            error("!rept 2\n.equ N 1\n!endr"),
+            "test.asm:2: `.equ` cannot be repeated, define it before `!rept`"
+        );
+        assert_eq!(
+            code(".equ N 2\n!rept N\nADD A N $0\n!endr"),
+            code("ADD A $2 $0\nADD A $2 $0")
+        );
+    }
+
+    #[test]
+    fn directives_in_data() {
+        let program = assemble("HALT\n[[DATA]]\n!if 0\nHi\n!endif", &Options::default()).unwrap();
+        assert_eq!(program.data, b"\0!if 0\0Hi\0!endif\0");
+        assert_eq!(
+            error("!if 1\nHALT\n[[DATA]]\n!endif"),
+            "test.asm:1: not closed before `[[DATA]]`"
+        );
+    }
+
+    #[test]
+    fn includes_and_data() {
+        let dir = std::env::temp_dir().join(format!("novavm-asm-include-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lib.asm"), "ADD B $1 $0\n").unwrap();
+        fs::write(dir.join("data.asm"), "HALT\n[[DATA]]\nHi\n").unwrap();
+        let options = Options {
+            file: dir.join("main.asm").display().to_string(),
+            ..Options::default()
+        };
+        let assemble = |source: &str| assemble(source, &options);
+
+        // Includes in data are data, an indented marker still starts the data section
+        let program = assemble("!include \"lib.asm\"\n  [[DATA]]\n!include \"lib.asm\"").unwrap();
+        assert_eq!(program.memory, code("ADD B $1 $0"));
+        assert_eq!(program.data, b"\0!include \"lib.asm\"\0");
+        assert_eq!(
+            assemble("!include \"data.asm\"\nHALT").unwrap_err().to_string(),
+            format!(
+                "{}:2: `[[DATA]]` in an included file, data goes in the including file\n  included from {}:1",
+                dir.join("data.asm").display(),
+                options.file
+            )
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
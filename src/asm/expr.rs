@@ -0,0 +1,403 @@
+use std::fmt;
+
+use super::literal;
+
+/// What a value is relative to, only matters when assembling an object
+/// - Absolute: A plain number
+/// - Code    : An address in the object's code, moves when linked
+/// - Data    : An address in the object's data, moves when linked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Base {
+    Absolute,
+    Code,
+    Data,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Value {
+    pub value: i32,
+    pub base: Base,
+}
+
+impl Value {
+    pub fn absolute(value: i32) -> Self {
+        Self {
+            value,
+            base: Base::Absolute,
+        }
+    }
+}
+
+/// Names an expression can refer to
+pub trait Scope {
+    /// Value of a label or constant
+    fn name(&mut self, name: &str) -> Result<Value, String>;
+    /// Size in bytes of what a label marks
+    fn size_of(&mut self, name: &str) -> Result<i32, String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    LogicalNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or,
+    LogicalAnd,
+    LogicalOr,
+}
+
+impl BinaryOp {
+    /// Binding strength, higher binds tighter, same as in C
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Mul | Self::Div | Self::Rem => 9,
+            Self::Add | Self::Sub => 8,
+            Self::Shl | Self::Shr => 7,
+            Self::Lt | Self::Le | Self::Gt | Self::Ge => 6,
+            Self::Eq | Self::Ne => 5,
+            Self::And => 4,
+            Self::Xor => 3,
+            Self::Or => 2,
+            Self::LogicalAnd => 1,
+            Self::LogicalOr => 0,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Rem => "%",
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::And => "&",
+            Self::Xor => "^",
+            Self::Or => "|",
+            Self::LogicalAnd => "&&",
+            Self::LogicalOr => "||",
+        }
+    }
+}
+
+/// Constant expression, evaluated once every label is placed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i32),
+    Name(String),
+    SizeOf(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i32),
+    Name(String),
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    Open,
+    Close,
+}
+
+/// Splits an expression into tokens, spaces are ignored
+/// Numbers are any integer literal, see [`literal::parse`]
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = s.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..].iter().collect();
+        if let Some((len, token)) = operator(&rest) {
+            i += len;
+            tokens.push(token);
+            continue;
+        }
+        let token = match c {
+            ' ' | '\t' => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '\'' => {
+                // Up to the closing quote, skipping an escaped one
+                let len = chars[i + 1..]
+                    .iter()
+                    .scan(false, |escaped, &c| {
+                        let end = c == '\'' && !*escaped;
+                        *escaped = c == '\\' && !*escaped;
+                        Some(end)
+                    })
+                    .position(|end| end)
+                    .ok_or(format!("unterminated character in `{}`", s))?
+                    + 2;
+                let word: String = chars[i..i + len].iter().collect();
+                i += len;
+                tokens.push(word_token(&word)?);
+                continue;
+            }
+            '$' | '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' => {
+                // `$-12` is a single literal
+                let sign = usize::from(c == '$' && chars.get(i + 1) == Some(&'-'));
+                let len = chars[i + 1 + sign..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count()
+                    + 1
+                    + sign;
+                let word: String = chars[i..i + len].iter().collect();
+                i += len;
+                tokens.push(word_token(&word)?);
+                continue;
+            }
+            _ => return Err(format!("unexpected `{}` in `{}`", c, s)),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// Operator at the start of `s`, with its length
+fn operator(s: &str) -> Option<(usize, Token)> {
+    let binary = [
+        ("<<", BinaryOp::Shl),
+        (">>", BinaryOp::Shr),
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("&&", BinaryOp::LogicalAnd),
+        ("||", BinaryOp::LogicalOr),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+        ("+", BinaryOp::Add),
+        ("-", BinaryOp::Sub),
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+        ("&", BinaryOp::And),
+        ("|", BinaryOp::Or),
+        ("^", BinaryOp::Xor),
+    ];
+    let unary = [("~", UnaryOp::Not), ("!", UnaryOp::LogicalNot)];
+    binary
+        .iter()
+        .find(|(symbol, _)| s.starts_with(symbol))
+        .map(|&(symbol, op)| (symbol.len(), Token::Binary(op)))
+        .or_else(|| {
+            unary
+                .iter()
+                .find(|(symbol, _)| s.starts_with(symbol))
+                .map(|&(symbol, op)| (symbol.len(), Token::Unary(op)))
+        })
+}
+
+fn word_token(word: &str) -> Result<Token, String> {
+    match literal::parse(word) {
+        Some(number) => number.map(Token::Number),
+        None => Ok(Token::Name(word.to_string())),
+    }
+}
+
+/// Recursive descent over the tokens, binary operators by precedence climbing
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token, what: &str) -> Result<(), String> {
+        if self.next() == Some(token) {
+            Ok(())
+        } else {
+            Err(format!("expected {}", what))
+        }
+    }
+
+    fn binary(&mut self, min: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(&Token::Binary(op)) = self.peek() {
+            if op.precedence() < min {
+                break;
+            }
+            self.at += 1;
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) if name == "sizeof" => {
+                self.expect(Token::Open, "`(` after sizeof")?;
+                let Some(Token::Name(label)) = self.next() else {
+                    return Err("expected a label in sizeof".to_string());
+                };
+                self.expect(Token::Close, "`)` after sizeof's label")?;
+                Ok(Expr::SizeOf(label))
+            }
+            Some(Token::Name(name)) => Ok(Expr::Name(name)),
+            Some(Token::Binary(BinaryOp::Sub)) => {
+                Ok(Expr::Unary(UnaryOp::Neg, Box::new(self.unary()?)))
+            }
+            Some(Token::Binary(BinaryOp::Add)) => self.unary(),
+            Some(Token::Unary(op)) => Ok(Expr::Unary(op, Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let e = self.binary(0)?;
+                self.expect(Token::Close, "`)`")?;
+                Ok(e)
+            }
+            _ => Err("expected a value".to_string()),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            at: 0,
+        };
+        let e = parser.binary(0).map_err(|e| format!("{} in `{}`", e, s))?;
+        if parser.at < parser.tokens.len() {
+            return Err(format!("unexpected tokens at the end of `{}`", s));
+        }
+        Ok(e)
+    }
+
+    /// Whether `s` is a lone operator, like the `-` of `end - start` without parentheses
+    pub fn is_operator(s: &str) -> bool {
+        operator(s).is_some_and(|(len, _)| len == s.len())
+    }
+
+    /// Whether `s` has to be read as an expression rather than a single literal or name
+    pub fn is_expression(s: &str) -> bool {
+        // `%` used to start base 64 literals, they are reported as literals, and `!` starts
+        // directives, an operand can only start with `!` inside parentheses
+        !s.starts_with(['%', '!'])
+            && tokenize(s).is_ok_and(|tokens| {
+                tokens.len() > 1 || matches!(tokens.first(), Some(Token::Name(n)) if n == "sizeof")
+            })
+    }
+
+    /// Evaluates with checked arithmetic, overflowing an `i32` is an error
+    /// Relocatable values only allow adding or subtracting a constant, and the difference of two
+    /// values with the same base
+    pub fn eval(&self, scope: &mut dyn Scope) -> Result<Value, String> {
+        match self {
+            Self::Number(n) => Ok(Value::absolute(*n)),
+            Self::Name(name) => scope.name(name),
+            Self::SizeOf(name) => Ok(Value::absolute(scope.size_of(name)?)),
+            Self::Unary(op, e) => {
+                let v = e.eval(scope)?;
+                if v.base != Base::Absolute {
+                    return Err(format!("`{}` is an address, it can't be negated", e));
+                }
+                let value = match op {
+                    UnaryOp::Neg => v.value.checked_neg(),
+                    UnaryOp::Not => Some(!v.value),
+                    UnaryOp::LogicalNot => Some((v.value == 0) as i32),
+                };
+                value
+                    .map(Value::absolute)
+                    .ok_or(format!("overflow in `{}`", self))
+            }
+            Self::Binary(op, l, r) => {
+                let (a, b) = (l.eval(scope)?, r.eval(scope)?);
+                let base = match (op, a.base, b.base) {
+                    (_, Base::Absolute, Base::Absolute) => Base::Absolute,
+                    (BinaryOp::Add, base, Base::Absolute)
+                    | (BinaryOp::Add, Base::Absolute, base) => base,
+                    (BinaryOp::Sub, base, Base::Absolute) => base,
+                    (BinaryOp::Sub, x, y) if x == y => Base::Absolute,
+                    _ => return Err(format!("`{}` can't be relocated", self)),
+                };
+                let (x, y) = (a.value, b.value);
+                let value = match op {
+                    BinaryOp::Add => x.checked_add(y),
+                    BinaryOp::Sub => x.checked_sub(y),
+                    BinaryOp::Mul => x.checked_mul(y),
+                    BinaryOp::Div | BinaryOp::Rem if y == 0 => {
+                        return Err(format!("division by zero in `{}`", self))
+                    }
+                    BinaryOp::Div => x.checked_div(y),
+                    BinaryOp::Rem => x.checked_rem(y),
+                    BinaryOp::Shl => u32::try_from(y)
+                        .ok()
+                        .filter(|&y| y < 32)
+                        .and_then(|y| i32::try_from((x as i64) << y).ok()),
+                    BinaryOp::Shr => u32::try_from(y).ok().filter(|&y| y < 32).map(|y| x >> y),
+                    BinaryOp::Lt => Some((x < y) as i32),
+                    BinaryOp::Le => Some((x <= y) as i32),
+                    BinaryOp::Gt => Some((x > y) as i32),
+                    BinaryOp::Ge => Some((x >= y) as i32),
+                    BinaryOp::Eq => Some((x == y) as i32),
+                    BinaryOp::Ne => Some((x != y) as i32),
+                    BinaryOp::LogicalAnd => Some((x != 0 && y != 0) as i32),
+                    BinaryOp::LogicalOr => Some((x != 0 || y != 0) as i32),
+                    BinaryOp::And => Some(x & y),
+                    BinaryOp::Xor => Some(x ^ y),
+                    BinaryOp::Or => Some(x | y),
+                };
+                value
+                    .map(|value| Value { value, base })
+                    .ok_or(format!("overflow in `{}`", self))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::Name(name) => write!(f, "{}", name),
+            Self::SizeOf(name) => write!(f, "sizeof({})", name),
+            Self::Unary(UnaryOp::Neg, e) => write!(f, "-{}", e),
+            Self::Unary(UnaryOp::Not, e) => write!(f, "~{}", e),
+            Self::Unary(UnaryOp::LogicalNot, e) => write!(f, "!{}", e),
+            Self::Binary(op, l, r) => write!(f, "({} {} {})", l, op.symbol(), r),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/// Code of every line of a file, with comments removed and words separated by a single space
+/// - `; comment`         : up to the end of the line
+/// - `;; block comment ;;`: up to the next `;;`, can span lines
+/// - Spaces, tabs and commas all separate words, `ADD R1, $1,  $2` is `ADD R1 $1 $2`
+///
+/// Characters, strings and parenthesized expressions are single words, `;` and `,` in a
+/// character or a string are kept. Lines from `[[DATA]]` on are data and kept as they are.
+/// An unterminated block comment or unbalanced parentheses are an error with the index of the line
+pub fn code_lines(lines: &[String]) -> Result<Vec<String>, (usize, String)> {
+    let mut code = Vec::with_capacity(lines.len());
+    // Line the current block comment started on
+    let mut block: Option<usize> = None;
+    for (index, line) in lines.iter().enumerate() {
+        if block.is_none() && data_marker(line).is_some() {
+            code.extend(lines[index..].iter().cloned());
+            return Ok(code);
+        }
+        let text = strip_comments(line, index, &mut block);
+        code.push(words(&text).map_err(|e| (index, e))?.join(" "));
+    }
+    match block {
+        Some(start) => Err((start, "unterminated block comment".to_string())),
+        None => Ok(code),
+    }
+}
+
+/// What follows `[[DATA]]` if `line` starts the data section, leading whitespace is ignored
+pub fn data_marker(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix("[[DATA]]")
+}
+
+/// `line` without its comments, `block` is where an open block comment started
+fn strip_comments(line: &str, index: usize, block: &mut Option<usize>) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if block.is_some() {
+            if c == ';' && chars.peek() == Some(&';') {
+                chars.next();
+                *block = None;
+                text.push(' ');
+            }
+            continue;
+        }
+        match c {
+            ';' if chars.peek() == Some(&';') => {
+                chars.next();
+                *block = Some(index);
+            }
+            ';' => break,
+            '\'' | '"' => {
+                text.push(c);
+                let mut escaped = false;
+                for q in chars.by_ref() {
+                    text.push(q);
+                    if q == c && !escaped {
+                        break;
+                    }
+                    escaped = q == '\\' && !escaped;
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// Words of a line of code, separated by spaces, tabs or commas
+/// A parenthesized expression, a character or a string is one word even if it has separators
+/// Parentheses outside of characters and strings must be balanced
+pub fn words(text: &str) -> Result<Vec<String>, String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        let separator = c.is_whitespace() || c == ',';
+        if separator && depth == 0 && quote.is_none() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (_, Some(q)) if c == q && !escaped => quote = None,
+            ('(', None) => depth += 1,
+            (')', None) if depth == 0 => return Err("unmatched `)`".to_string()),
+            (')', None) => depth -= 1,
+            _ => {}
+        }
+        escaped = quote.is_some() && c == '\\' && !escaped;
+        word.push(c);
+    }
+    if depth > 0 {
+        return Err("unclosed `(`".to_string());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
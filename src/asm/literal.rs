@@ -0,0 +1,181 @@
+/// Values an 8-bit operand can hold
+pub const UNSIGNED: std::ops::RangeInclusive<i32> = 0..=255;
+/// Values a sign-extended operand can hold, stored as two's complement
+pub const SIGNED: std::ops::RangeInclusive<i32> = -128..=127;
+
+/// Parses an integer literal, `None` when `s` does not look like one
+/// - `12`, `$12`: decimal, `$` is optional
+/// - `0x1F`     : hexadecimal
+/// - `0b1010`   : binary
+/// - `0o17`     : octal
+/// - `'a'`      : a character, with the escapes `\n \t \r \0 \\ \' \xHH`
+///
+/// Every number can be negative, `-12` or `$-12`, and have `_` between digits, `0b1010_0101`
+pub fn parse(s: &str) -> Option<Result<i32, String>> {
+    let (negative, unsigned) = match s.strip_prefix('$').unwrap_or(s).strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('$').unwrap_or(s)),
+    };
+    let value = if unsigned.starts_with('\'') {
+        char_literal(unsigned)
+    } else if unsigned.starts_with('%') {
+        Err(format!(
+            "`{}`: base 64 literals are not supported, use 0x, 0b or 0o",
+            s
+        ))
+    } else if unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+        let (radix, digits) = match unsigned.get(..2) {
+            Some("0x") => (16, &unsigned[2..]),
+            Some("0b") => (2, &unsigned[2..]),
+            Some("0o") => (8, &unsigned[2..]),
+            _ => (10, unsigned),
+        };
+        number(digits, radix).map_err(|e| format!("`{}`: {}", s, e))
+    } else if s.starts_with('$') {
+        Err(format!("`{}` is not a number", s))
+    } else if let Some(number) = unsigned.strip_prefix('$') {
+        Err(format!("`{}`: the sign goes after `$`, `$-{}`", s, number))
+    } else {
+        return None;
+    };
+    Some(value.map(|v| if negative { -v } else { v }))
+}
+
+/// Digits in `radix`, with optional `_` separators between them
+fn number(digits: &str, radix: u32) -> Result<i32, String> {
+    if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+        return Err("expected digits".to_string());
+    }
+    let digits = digits.replace('_', "");
+    if let Some(c) = digits.chars().find(|c| !c.is_digit(radix)) {
+        return Err(format!("`{}` is not a base {} digit", c, radix));
+    }
+    i32::from_str_radix(&digits, radix).map_err(|_| "too large".to_string())
+}
+
+/// `'a'` or an escaped character, its value has to fit in a byte
+fn char_literal(s: &str) -> Result<i32, String> {
+    let inner = s
+        .strip_prefix('\'')
+        .and_then(|s| s.strip_suffix('\''))
+        .filter(|s| !s.is_empty())
+        .ok_or(format!("`{}` is not a character literal", s))?;
+    let value = match inner.strip_prefix('\\') {
+        None if inner.chars().count() == 1 => inner.chars().next().unwrap() as u32,
+        Some("n") => b'\n' as u32,
+        Some("t") => b'\t' as u32,
+        Some("r") => b'\r' as u32,
+        Some("0") => 0,
+        Some("\\") => b'\\' as u32,
+        Some("'") => b'\'' as u32,
+        Some(hex) if hex.starts_with('x') => match hex.get(1..) {
+            Some(digits) if digits.len() == 2 && digits.chars().all(|c| c.is_ascii_hexdigit()) => {
+                u32::from_str_radix(digits, 16).unwrap()
+            }
+            _ => {
+                return Err(format!(
+                    "`{}` is not a valid escape, `\\x` takes two hex digits",
+                    s
+                ))
+            }
+        },
+        _ => return Err(format!("`{}` is not a single character", s)),
+    };
+    if value > 0xFF {
+        return Err(format!("`{}` does not fit in a byte", s));
+    }
+    Ok(value as i32)
+}
+
+/// `value` as an operand, an error when it does not fit in 8 bits
+/// Only `signed` operands, sign-extended by the machine, can be negative
+pub fn operand(text: &str, value: i32, signed: bool) -> Result<u8, String> {
+    let range = if signed { SIGNED } else { UNSIGNED };
+    if range.contains(&value) {
+        Ok(value as u8)
+    } else if value < 0 && !signed {
+        Err(format!(
+            "`{}` is {}, only the immediates of IMUL, IDIV, IMOD and ICMP can be negative",
+            text, value
+        ))
+    } else {
+        Err(format!(
+            "`{}` is {}, it does not fit in {} 8-bit operand",
+            text,
+            value,
+            if signed { "a signed" } else { "an" }
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(s: &str) -> String {
+        parse(s).unwrap().unwrap_err()
+    }
+
+    #[test]
+    fn bases() {
+        assert_eq!(parse("12"), Some(Ok(12)));
+        assert_eq!(parse("$12"), Some(Ok(12)));
+        assert_eq!(parse("0x1F"), Some(Ok(0x1F)));
+        assert_eq!(parse("$0b1010_0101"), Some(Ok(0b1010_0101)));
+        assert_eq!(parse("0o17"), Some(Ok(0o17)));
+        assert_eq!(parse("-0x10"), Some(Ok(-16)));
+        assert_eq!(parse("1_000"), Some(Ok(1000)));
+        assert_eq!(parse("name"), None);
+        assert_eq!(error("0b102"), "`0b102`: `2` is not a base 2 digit");
+        assert_eq!(error("0x"), "`0x`: expected digits");
+        assert_eq!(error("1__"), "`1__`: expected digits");
+        assert_eq!(error("0x_1"), "`0x_1`: expected digits");
+        assert_eq!(error("99999999999"), "`99999999999`: too large");
+        assert_eq!(
+            error("%10"),
+            "`%10`: base 64 literals are not supported, use 0x, 0b or 0o"
+        );
+        assert_eq!(error("$x"), "`$x` is not a number");
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(parse("'a'"), Some(Ok(97)));
+        assert_eq!(parse("$'a'"), Some(Ok(97)));
+        assert_eq!(parse("'\\n'"), Some(Ok(10)));
+        assert_eq!(parse("'\\''"), Some(Ok(39)));
+        assert_eq!(parse("'\\xff'"), Some(Ok(0xFF)));
+        assert_eq!(parse("'é'"), Some(Ok(0xE9)));
+        assert_eq!(error("'ab'"), "`'ab'` is not a single character");
+        assert_eq!(error("'\\q'"), "`'\\q'` is not a single character");
+        assert_eq!(error("''"), "`''` is not a character literal");
+        assert_eq!(error("'€'"), "`'€'` does not fit in a byte");
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(operand("x", 255, false), Ok(0xFF));
+        assert_eq!(operand("x", -128, true), Ok(0x80));
+        assert_eq!(
+            operand("x", 256, false).unwrap_err(),
+            "`x` is 256, it does not fit in an 8-bit operand"
+        );
+        assert_eq!(
+            operand("x", 128, true).unwrap_err(),
+            "`x` is 128, it does not fit in a signed 8-bit operand"
+        );
+    }
+
+    #[test]
+    fn misplaced_signs() {
+        assert_eq!(
+            error("'\\x+1'"),
+            "`'\\x+1'` is not a valid escape, `\\x` takes two hex digits"
+        );
+        assert!(parse("'\\x-1'").unwrap().is_err());
+        assert!(parse("'\\x1'").unwrap().is_err());
+        assert_eq!(parse("'\\x7F'"), Some(Ok(0x7F)));
+        assert_eq!(error("-$5"), "`-$5`: the sign goes after `$`, `$-5`");
+        assert_eq!(parse("$-5"), Some(Ok(-5)));
+    }
+}
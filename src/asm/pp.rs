@@ -0,0 +1,880 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::fpu::FRegister;
+use crate::lines::{LineTable, SourceLine};
+use crate::object::{Object, Relocation};
+use crate::opcode::OpCode;
+use crate::program::Program;
+use crate::symbols::SymbolTable;
+use crate::syscall::Syscall;
+use crate::Register;
+
+use super::expr::{Base, Expr, Scope, Value};
+use super::{lexer, literal, Diagnostic};
+
+#[derive(Debug, Clone)]
+enum Part {
+    OpCode(OpCode),
+    Register(Register),
+    FRegister(FRegister),
+    Syscall(Syscall),
+    Number(u8),
+    Float(f64),
+    Label(String),
+    Expr(Expr),
+}
+
+/// A line of source, after includes are expanded
+#[derive(Debug, Clone)]
+struct Line {
+    source: SourceLine,
+    /// Where the file of this line was included from, innermost first
+    included_from: Rc<Vec<SourceLine>>,
+    text: String,
+}
+
+impl Line {
+    /// Error pointing at this line and its include chain
+    fn diagnostic(&self, message: &str) -> Diagnostic {
+        Diagnostic {
+            source: Some(self.source.clone()),
+            included_from: self.included_from.to_vec(),
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PreProcessor {
+    /// Input file name
+    file: String,
+    /// Input
+    lines: Vec<String>,
+    /// Directories searched by `!include`, after the including file's own directory
+    include_paths: Vec<PathBuf>,
+    /// Memory
+    memory: Vec<u8>,
+    /// Data
+    data: Vec<u8>,
+    /// Labels
+    symbols: SymbolTable,
+    /// Source line of every instruction
+    line_table: LineTable,
+    /// Assemble a relocatable object instead of a program
+    object: bool,
+    /// Labels made visible to other objects with `!global`
+    exports: Vec<String>,
+    /// Symbols from other objects, declared with `!extern`
+    imports: Vec<String>,
+    /// Operands to patch when linking
+    relocations: Vec<(u16, Relocation)>,
+    /// Labels in the data section, with their offset in data
+    data_labels: Vec<(String, u16)>,
+    /// Constants defined before the source, with `-D`
+    defines: Vec<(String, Expr)>,
+}
+
+impl PreProcessor {
+    pub fn new(file: &str, lines: Vec<String>) -> Self {
+        Self {
+            file: file.to_string(),
+            lines,
+            include_paths: Vec::new(),
+            memory: Vec::new(),
+            data: Vec::new(),
+            symbols: SymbolTable::new(),
+            line_table: LineTable::new(),
+            object: false,
+            exports: Vec::new(),
+            imports: Vec::new(),
+            relocations: Vec::new(),
+            data_labels: Vec::new(),
+            defines: Vec::new(),
+        }
+    }
+
+    /// Assembles a relocatable object, for the linker, instead of a program
+    pub fn object_mode(&mut self) {
+        self.object = true;
+    }
+
+    /// Defines the constant `name`, as if the source started with `.equ name value`
+    pub fn define(&mut self, name: &str, value: &str) -> Result<(), Diagnostic> {
+        check_name(name).map_err(|e| format!("-D: {}", e))?;
+        let value = Expr::parse(value).map_err(|e| format!("-D {}: {}", name, e))?;
+        self.defines.push((name.to_string(), value));
+        Ok(())
+    }
+
+    /// Adds a directory searched by `!include`
+    pub fn include_path(&mut self, dir: &Path) {
+        self.include_paths.push(dir.to_path_buf());
+    }
+
+    pub fn parse(&mut self) -> Result<(), Diagnostic> {
+        let mut parts: Vec<Part> = Vec::new();
+
+        let mut data_section: Vec<u8> = Vec::new();
+        let mut in_data_section = false;
+
+        let lines = self.expand_includes()?;
+        let lines = expand_directives(lines, &self.defines)?;
+        let labels = label_names(&lines);
+        let constants = constant_definitions(&lines, &labels, &self.defines)?;
+        let data_names = data_label_names(&lines);
+
+        // Line every part comes from, for diagnostics
+        let mut part_lines: Vec<usize> = Vec::new();
+        // Parts that are sign-extended immediates
+        let mut signed_parts: HashSet<usize> = HashSet::new();
+
+        for (index, line) in lines.iter().enumerate() {
+            let text = &line.text;
+            let data_contents = lexer::data_marker(text);
+            in_data_section |= data_contents.is_some();
+            if in_data_section {
+                // Code lines have no comments left, data lines starting with `;` are comments
+                if text.starts_with(';') {
+                    continue;
+                }
+                let contents = data_contents.unwrap_or(text).trim();
+                if let Some(label) = data_label(contents).map_err(|e| line.diagnostic(&e))? {
+                    if self.symbols.get(label).is_some()
+                        || self.data_labels.iter().any(|(n, _)| n == label)
+                    {
+                        let e = format!("label `{}` is defined twice", label);
+                        return Err(line.diagnostic(&e));
+                    }
+                    self.data_labels
+                        .push((label.to_string(), data_section.len() as u16));
+                } else {
+                    data_section.extend(data_bytes(contents).map_err(|e| line.diagnostic(&e))?);
+                }
+                continue;
+            }
+
+            if text.trim().starts_with(".equ ") {
+                continue;
+            }
+
+            if let Some(name) = text.trim().strip_prefix("!global ") {
+                self.exports.push(name.trim().to_string());
+                continue;
+            }
+            if let Some(name) = text.trim().strip_prefix("!extern ") {
+                if !self.object {
+                    return Err(line.diagnostic(&format!(
+                        "`{}` is external, assemble with -c and link",
+                        name.trim()
+                    )));
+                }
+                self.imports.push(name.trim().to_string());
+                continue;
+            }
+
+            let start = parts.len();
+            for word in lexer::words(text).map_err(|e| line.diagnostic(&e))? {
+                let word = word.as_str();
+                let signed = signed_operand(&parts[start..]);
+                if signed {
+                    signed_parts.insert(parts.len());
+                }
+                if let Some(label) = label_definition(word).map_err(|e| line.diagnostic(&e))? {
+                    if self.symbols.get(label).is_some() {
+                        let e = format!("label `{}` is defined twice", label);
+                        return Err(line.diagnostic(&e));
+                    }
+                    self.symbols.insert(label, parts.len() as u16);
+                } else if let Some(part) = keyword(word) {
+                    parts.push(part);
+                } else if labels.iter().any(|l| l == word) {
+                    parts.push(Part::Label(word.to_string()));
+                } else if Expr::is_expression(word)
+                    || constants.contains_key(word)
+                    || data_names.iter().any(|l| l == word)
+                {
+                    let expr = Expr::parse(word).map_err(|e| line.diagnostic(&e))?;
+                    parts.push(Part::Expr(expr));
+                } else {
+                    parts.push(parse_word(word, signed).map_err(|e| line.diagnostic(&e))?);
+                }
+            }
+            if parts.len() > start {
+                self.line_table.insert(start as u16, line.source.clone());
+                part_lines.resize(parts.len(), index);
+            }
+        }
+
+        // `sizeof` of the last data label stops at the program's own data
+        let data_end = data_section.len() as u16;
+        // Float literals can't fit in an operand, they are put in data after the program's own
+        // data, and the operand becomes their address
+        for (address, part) in parts.iter_mut().enumerate() {
+            if let Part::Float(v) = *part {
+                let offset = data_section.len() as i32;
+                *part = Part::Number(
+                    literal::operand(&format!("${}", v), offset, false)
+                        .map_err(|e| lines[part_lines[address]].diagnostic(&e))?,
+                );
+                data_section.extend_from_slice(&v.to_le_bytes());
+                self.relocations.push((address as u16, Relocation::Data));
+            }
+        }
+
+        for (address, part) in parts.iter().enumerate() {
+            if let Part::Label(l) = part {
+                let relocation = if self.imports.contains(l) {
+                    Relocation::Symbol(l.clone())
+                } else {
+                    Relocation::Code
+                };
+                self.relocations.push((address as u16, relocation));
+            }
+        }
+
+        // Every label is placed, constant expressions can be evaluated
+        let mut names = Names {
+            symbols: &self.symbols,
+            code_end: parts.len() as u16,
+            data_labels: &self.data_labels,
+            data_end,
+            constants: &constants,
+            imports: &self.imports,
+            object: self.object,
+            evaluating: Vec::new(),
+        };
+        for (name, (_, index)) in constants.iter() {
+            names.name(name).map_err(|e| match index {
+                Some(index) => lines[*index].diagnostic(&e),
+                None => format!("-D {}: {}", name, e).into(),
+            })?;
+        }
+        let mut relocations = Vec::new();
+        for (address, part) in parts.iter_mut().enumerate() {
+            let Part::Expr(expr) = part else {
+                continue;
+            };
+            let diagnostic = |e: &str| lines[part_lines[address]].diagnostic(e);
+            let v = expr.eval(&mut names).map_err(|e| diagnostic(&e))?;
+            let signed = signed_parts.contains(&address);
+            let value =
+                literal::operand(&expr.to_string(), v.value, signed).map_err(|e| diagnostic(&e))?;
+            match v.base {
+                Base::Code => relocations.push((address as u16, Relocation::Code)),
+                Base::Data => relocations.push((address as u16, Relocation::Data)),
+                Base::Absolute => {}
+            }
+            *part = Part::Number(value);
+        }
+        self.relocations.extend(relocations);
+        self.relocations.sort_by_key(|(address, _)| *address);
+
+        for name in &self.exports {
+            if self.symbols.get(name).is_none() {
+                return Err(
+                    format!("{}: `{}` is exported but not defined", self.file, name).into(),
+                );
+            }
+        }
+
+        let parsed_parts: Vec<u8> = parts
+            .iter()
+            .enumerate()
+            .map(|(address, p)| {
+                self.parse_part_into_u8(p)
+                    .map_err(|e| lines[part_lines[address]].diagnostic(&e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.memory.extend_from_slice(&parsed_parts);
+        self.data.extend_from_slice(&data_section);
+
+        Ok(())
+    }
+
+    pub fn into_object(self) -> Object {
+        Object {
+            name: self.file.clone(),
+            exports: self.exports.clone(),
+            imports: self.imports.clone(),
+            relocations: self.relocations.clone(),
+            program: self.program(),
+        }
+    }
+
+    pub fn program(self) -> Program {
+        Program {
+            memory: self.memory,
+            data: self.data,
+            symbols: self.symbols,
+            lines: self.line_table,
+            version: Program::VERSION,
+        }
+    }
+
+    /// Input lines with every `!include "path"` replaced by the lines of that file
+    /// Every file is included once, including a file that is still being included is an error
+    /// Only code is expanded, the data section belongs to the input file alone
+    fn expand_includes(&self) -> Result<Vec<Line>, Diagnostic> {
+        let mut lines = Vec::new();
+        let path = PathBuf::from(&self.file);
+        let mut stack = vec![canonical(&path)];
+        let mut seen: HashSet<PathBuf> = stack.iter().cloned().collect();
+        self.expand_file(
+            &path,
+            &self.lines,
+            Rc::new(Vec::new()),
+            &mut stack,
+            &mut seen,
+            &mut lines,
+        )?;
+        Ok(lines)
+    }
+
+    fn expand_file(
+        &self,
+        path: &Path,
+        text: &[String],
+        included_from: Rc<Vec<SourceLine>>,
+        stack: &mut Vec<PathBuf>,
+        seen: &mut HashSet<PathBuf>,
+        lines: &mut Vec<Line>,
+    ) -> Result<(), Diagnostic> {
+        let line = |number: usize, text: String| Line {
+            source: SourceLine {
+                file: path.display().to_string(),
+                line: number as u32 + 1,
+            },
+            included_from: included_from.clone(),
+            text,
+        };
+        let code = lexer::code_lines(text)
+            .map_err(|(number, e)| line(number, String::new()).diagnostic(&e))?;
+        for (number, text) in code.iter().enumerate() {
+            if lexer::data_marker(text).is_some() {
+                if !included_from.is_empty() {
+                    return Err(line(number, text.clone()).diagnostic(
+                        "`[[DATA]]` in an included file, data goes in the including file",
+                    ));
+                }
+                let data = code.iter().enumerate().skip(number);
+                lines.extend(data.map(|(number, text)| line(number, text.clone())));
+                return Ok(());
+            }
+            let line = line(number, text.clone());
+            let Some(target) = text.trim().strip_prefix("!include") else {
+                lines.push(line);
+                continue;
+            };
+
+            let name = target
+                .trim()
+                .strip_prefix('"')
+                .and_then(|t| t.strip_suffix('"'))
+                .ok_or_else(|| line.diagnostic("expected `!include \"path\"`"))?;
+            let found = self
+                .find_include(path, name)
+                .ok_or_else(|| line.diagnostic(&format!("cannot find `{}`", name)))?;
+            let key = canonical(&found);
+            if let Some(start) = stack.iter().position(|p| *p == key) {
+                let cycle: Vec<_> = stack[start..]
+                    .iter()
+                    .chain([&key])
+                    .map(|p| p.display().to_string())
+                    .collect();
+                return Err(line.diagnostic(&format!("include cycle: {}", cycle.join(" -> "))));
+            }
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+
+            let contents = fs::read_to_string(&found).map_err(|e| {
+                line.diagnostic(&format!("cannot read `{}`: {}", found.display(), e))
+            })?;
+            let included: Vec<String> = contents.lines().map(String::from).collect();
+            let mut chain = vec![line.source.clone()];
+            chain.extend(included_from.iter().cloned());
+
+            stack.push(key);
+            self.expand_file(&found, &included, Rc::new(chain), stack, seen, lines)?;
+            stack.pop();
+        }
+        Ok(())
+    }
+
+    /// Looks for `name` next to `from`, then in every include path
+    fn find_include(&self, from: &Path, name: &str) -> Option<PathBuf> {
+        let own_dir = from.parent().map(Path::to_path_buf).unwrap_or_default();
+        std::iter::once(own_dir)
+            .chain(self.include_paths.iter().cloned())
+            .map(|dir| dir.join(name))
+            .find(|p| p.is_file())
+    }
+
+    fn parse_part_into_u8(&self, p: &Part) -> Result<u8, String> {
+        Ok(match p {
+            Part::OpCode(o) => *o as u8,
+            Part::Register(r) => *r as u8,
+            Part::FRegister(f) => *f as u8,
+            Part::Syscall(s) => *s as u8,
+            Part::Number(x) => *x,
+            // Filled in by the linker
+            Part::Label(l) if self.imports.contains(l) => 0,
+            Part::Label(l) => {
+                let address = self
+                    .symbols
+                    .get(l)
+                    .ok_or(format!("undefined label `{}`", l))?;
+                literal::operand(l, address as i32, false)?
+            }
+            // `parse` turns every float and expression into a number before encoding
+            Part::Float(_) | Part::Expr(_) => unreachable!("{:?} is not encoded yet", p),
+        })
+    }
+}
+
+/// Names of every label defined in the code or imported, so labels can be used before their definition
+fn label_names(lines: &[Line]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .take_while(|text| lexer::data_marker(text).is_none())
+        .flat_map(|text| match text.trim().strip_prefix("!extern ") {
+            Some(name) => vec![name.trim().to_string()],
+            None => lexer::words(text)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|word| label_definition(word).ok().flatten())
+                .map(String::from)
+                .collect(),
+        })
+        .collect()
+}
+
+/// Constants by name, with the index of the line defining them, `None` for `-D` defines
+type Constants = HashMap<String, (Expr, Option<usize>)>;
+
+/// `.equ NAME expr` constants, they can be used anywhere in the code, even before their definition
+fn constant_definitions(
+    lines: &[Line],
+    labels: &[String],
+    defines: &[(String, Expr)],
+) -> Result<Constants, Diagnostic> {
+    let mut constants: Constants = defines
+        .iter()
+        .map(|(name, value)| (name.clone(), (value.clone(), None)))
+        .collect();
+    if let Some((name, _)) = defines.iter().find(|(name, _)| labels.contains(name)) {
+        return Err(format!("-D: `{}` is already a label", name).into());
+    }
+    for (index, line) in lines.iter().enumerate() {
+        if lexer::data_marker(&line.text).is_some() {
+            break;
+        }
+        let Some((name, expr)) = equ_definition(line)? else {
+            continue;
+        };
+        if labels.contains(&name) {
+            return Err(line.diagnostic(&format!("`{}` is already a label", name)));
+        }
+        if constants
+            .insert(name.clone(), (expr, Some(index)))
+            .is_some()
+        {
+            return Err(line.diagnostic(&format!("`{}` is defined twice", name)));
+        }
+    }
+    Ok(constants)
+}
+
+/// Name and value of a `.equ NAME expr` line
+fn equ_definition(line: &Line) -> Result<Option<(String, Expr)>, Diagnostic> {
+    let Some(definition) = line.text.trim().strip_prefix(".equ ") else {
+        return Ok(None);
+    };
+    let (name, expr) = definition
+        .trim()
+        .split_once(' ')
+        .ok_or_else(|| line.diagnostic("expected `.equ NAME expr`"))?;
+    check_name(name).map_err(|e| line.diagnostic(&e))?;
+    let expr = Expr::parse(expr).map_err(|e| line.diagnostic(&e))?;
+    Ok(Some((name.to_string(), expr)))
+}
+
+/// An open `!if` or `!rept` while expanding directives
+enum Block {
+    /// `active`: the current branch is assembled, `taken`: a branch was, `closed`: `!else` was seen
+    If {
+        line: Line,
+        active: bool,
+        taken: bool,
+        closed: bool,
+    },
+    /// `start`: where the body starts in the output
+    Rept {
+        line: Line,
+        count: usize,
+        start: usize,
+    },
+}
+
+impl Block {
+    fn line(&self) -> &Line {
+        match self {
+            Self::If { line, .. } | Self::Rept { line, .. } => line,
+        }
+    }
+
+    fn active(&self) -> bool {
+        match self {
+            Self::If { active, .. } => *active,
+            Self::Rept { count, .. } => *count > 0,
+        }
+    }
+}
+
+/// Most times a `!rept` body can be repeated
+const MAX_REPT: usize = 4096;
+
+/// Lines with conditional assembly and repetitions applied
+/// - `!if expr`, `!elif expr`, `!else`, `!endif`: only the first branch with a non-zero `expr`
+/// - `!ifdef NAME`, `!ifndef NAME`: whether `NAME` is defined with `.equ` or `-D` by then
+/// - `!rept count` ... `!endr`: the lines in between, `count` times, at most [`MAX_REPT`]
+///
+/// Expressions can use `-D` defines and the `.equ` constants defined before them, not labels.
+/// Directives are code, the data section is kept as it is and every block must be closed before it
+fn expand_directives(
+    lines: Vec<Line>,
+    defines: &[(String, Expr)],
+) -> Result<Vec<Line>, Diagnostic> {
+    let mut constants: Constants = defines
+        .iter()
+        .map(|(name, value)| (name.clone(), (value.clone(), None)))
+        .collect();
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut out: Vec<Line> = Vec::new();
+
+    let mut lines = lines.into_iter();
+    while let Some(line) = lines.next() {
+        if lexer::data_marker(&line.text).is_some() {
+            if let Some(block) = blocks.last() {
+                return Err(block.line().diagnostic("not closed before `[[DATA]]`"));
+            }
+            out.push(line);
+            out.extend(lines);
+            return Ok(out);
+        }
+        let text = line.text.trim();
+        let (directive, argument) = text.split_once(' ').unwrap_or((text, ""));
+        let argument = argument.trim();
+        let active = blocks.iter().all(Block::active);
+        let evaluate =
+            |constants: &Constants| condition(argument, constants).map_err(|e| line.diagnostic(&e));
+
+        match directive {
+            "!if" | "!ifdef" | "!ifndef" => {
+                let taken = active
+                    && match directive {
+                        "!if" => evaluate(&constants)? != 0,
+                        "!ifdef" => constants.contains_key(argument),
+                        _ => !constants.contains_key(argument),
+                    };
+                blocks.push(Block::If {
+                    line: line.clone(),
+                    active: taken,
+                    taken: taken || !active,
+                    closed: false,
+                });
+            }
+            "!elif" | "!else" => {
+                let Some(Block::If {
+                    active,
+                    taken,
+                    closed,
+                    ..
+                }) = blocks.last_mut()
+                else {
+                    return Err(line.diagnostic(&format!("`{}` without `!if`", directive)));
+                };
+                if *closed {
+                    return Err(line.diagnostic(&format!("`{}` after `!else`", directive)));
+                }
+                *closed = directive == "!else";
+                *active = !*taken && (*closed || evaluate(&constants)? != 0);
+                *taken |= *active;
+            }
+            "!endif" => match blocks.pop() {
+                Some(Block::If { .. }) => {}
+                _ => return Err(line.diagnostic("`!endif` without `!if`")),
+            },
+            "!rept" => {
+                let count = if active { evaluate(&constants)? } else { 0 };
+                let count = usize::try_from(count).map_err(|_| {
+                    line.diagnostic(&format!("`!rept` count {} is negative", count))
+                })?;
+                if count > MAX_REPT {
+                    let e = format!("`!rept` count {} is more than {}", count, MAX_REPT);
+                    return Err(line.diagnostic(&e));
+                }
+                blocks.push(Block::Rept {
+                    line: line.clone(),
+                    count,
+                    start: out.len(),
+                });
+            }
+            "!endr" => match blocks.pop() {
+                Some(Block::Rept { count, start, .. }) if count > 1 => {
+                    let body = out[start..].to_vec();
+                    for _ in 1..count {
+                        out.extend_from_slice(&body);
+                    }
+                }
+                Some(Block::Rept { .. }) => {}
+                _ => return Err(line.diagnostic("`!endr` without `!rept`")),
+            },
+            ".equ" if blocks.iter().any(|b| matches!(b, Block::Rept { .. })) => {
+                return Err(line.diagnostic("`.equ` cannot be repeated, define it before `!rept`"));
+            }
+            _ if active => {
+                if let Some((name, expr)) = equ_definition(&line)? {
+                    constants.insert(name, (expr, None));
+                }
+                out.push(line);
+            }
+            _ => {}
+        }
+    }
+
+    match blocks.last() {
+        Some(block) => Err(block.line().diagnostic("not closed")),
+        None => Ok(out),
+    }
+}
+
+/// Value of a `!if` or `!rept` expression, with only constants defined
+fn condition(expr: &str, constants: &Constants) -> Result<i32, String> {
+    let symbols = SymbolTable::new();
+    let mut names = Names {
+        symbols: &symbols,
+        code_end: 0,
+        data_labels: &[],
+        data_end: 0,
+        constants,
+        imports: &[],
+        object: false,
+        evaluating: Vec::new(),
+    };
+    Ok(Expr::parse(expr)?.eval(&mut names)?.value)
+}
+
+/// Names of the labels in the data section, defined with `.label name`
+fn data_label_names(lines: &[Line]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .skip_while(|text| lexer::data_marker(text).is_none())
+        .filter_map(|text| {
+            data_label(lexer::data_marker(text).unwrap_or(text).trim())
+                .ok()
+                .flatten()
+        })
+        .map(String::from)
+        .collect()
+}
+
+/// Names visible to constant expressions, once the code is laid out
+/// Outside of objects every address is final, so every value is absolute
+struct Names<'a> {
+    symbols: &'a SymbolTable,
+    code_end: u16,
+    data_labels: &'a [(String, u16)],
+    data_end: u16,
+    constants: &'a Constants,
+    imports: &'a [String],
+    object: bool,
+    /// Constants being evaluated, to catch definitions that refer to themselves
+    evaluating: Vec<String>,
+}
+
+impl Names<'_> {
+    fn value(&self, value: u16, base: Base) -> Value {
+        Value {
+            value: value as i32,
+            base: if self.object { base } else { Base::Absolute },
+        }
+    }
+}
+
+impl Scope for Names<'_> {
+    fn name(&mut self, name: &str) -> Result<Value, String> {
+        let constants = self.constants;
+        if let Some((expr, _)) = constants.get(name) {
+            if self.evaluating.iter().any(|n| n == name) {
+                return Err(format!("`{}` is defined in terms of itself", name));
+            }
+            self.evaluating.push(name.to_string());
+            let v = expr.eval(self);
+            self.evaluating.pop();
+            return v;
+        }
+        if self.imports.iter().any(|n| n == name) {
+            return Err(format!(
+                "`{}` is imported, it can only be used on its own",
+                name
+            ));
+        }
+        if let Some(address) = self.symbols.get(name) {
+            return Ok(self.value(address, Base::Code));
+        }
+        match self.data_labels.iter().find(|(n, _)| n == name) {
+            Some(&(_, offset)) => Ok(self.value(offset, Base::Data)),
+            None => Err(format!("undefined name `{}`", name)),
+        }
+    }
+
+    /// Bytes from the label to the next label, or to the end of its section
+    fn size_of(&mut self, name: &str) -> Result<i32, String> {
+        if let Some(address) = self.symbols.get(name) {
+            let end = self
+                .symbols
+                .iter()
+                .map(|(a, _)| a)
+                .find(|&a| a > address)
+                .unwrap_or(self.code_end);
+            return Ok((end - address) as i32);
+        }
+        let at = self
+            .data_labels
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or(format!("sizeof: `{}` is not a label", name))?;
+        let offset = self.data_labels[at].1;
+        let end = self.data_labels[at + 1..]
+            .iter()
+            .map(|&(_, o)| o)
+            .find(|&o| o > offset)
+            .unwrap_or(self.data_end);
+        Ok((end - offset) as i32)
+    }
+}
+
+/// Identity of a file for include-once and cycle checks
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Bytes of a line in the data section
+/// - `.f64 1.5 -2`: little endian `f64`s
+/// - `.label name`: no bytes, handled before
+/// - Anything else: the text itself, null terminated
+fn data_bytes(contents: &str) -> Result<Vec<u8>, String> {
+    if let Some(values) = contents.strip_prefix(".f64") {
+        let mut bytes = Vec::new();
+        for v in values.split_whitespace() {
+            let v: f64 = v
+                .parse()
+                .map_err(|_| format!("could not parse `{}` into a float", v))?;
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        Ok(bytes)
+    } else {
+        let mut bytes = contents.as_bytes().to_vec();
+        bytes.push(0);
+        Ok(bytes)
+    }
+}
+
+/// `name:` defines the label `name` at the current address, `None` for any other word
+/// Names of instructions, registers and syscalls are reserved, defining one is an error
+fn label_definition(word: &str) -> Result<Option<&str>, String> {
+    match word.strip_suffix(':').filter(|name| is_name(name)) {
+        Some(name) => check_name(name).map(|_| Some(name)),
+        None => Ok(None),
+    }
+}
+
+/// `.label name` labels the data that follows it, `None` for any other line of data
+/// Any other line is data, even `name:`, so that text like `Error:` stays text
+fn data_label(contents: &str) -> Result<Option<&str>, String> {
+    let Some(name) = contents.strip_prefix(".label ") else {
+        return Ok(None);
+    };
+    let name = name.trim();
+    check_name(name).map(|_| Some(name))
+}
+
+/// A letter or `_`, then letters, digits or `_`
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// An error when `name` cannot name a label or a constant
+fn check_name(name: &str) -> Result<(), String> {
+    if !is_name(name) {
+        return Err(format!("`{}` is not a valid name", name));
+    }
+    let reserved = match keyword(name) {
+        Some(Part::OpCode(_)) => "an instruction",
+        Some(Part::Register(_) | Part::FRegister(_)) => "a register",
+        Some(_) => "a syscall",
+        None => return Ok(()),
+    };
+    Err(format!(
+        "`{}` is {}, it cannot be redefined",
+        name, reserved
+    ))
+}
+
+/// Part for an instruction, register or syscall name
+fn keyword(s: &str) -> Option<Part> {
+    if let Ok(op) = OpCode::try_from(s) {
+        Some(Part::OpCode(op))
+    } else if let Ok(r) = Register::try_from(s) {
+        Some(Part::Register(r))
+    } else if let Ok(f) = FRegister::try_from(s) {
+        Some(Part::FRegister(f))
+    } else {
+        Syscall::try_from(s).ok().map(Part::Syscall)
+    }
+}
+
+/// Whether the next part of an instruction starting with `parts` is an immediate the machine
+/// sign-extends, the operands after the destination of `IMUL`, `IDIV`, `IMOD` and `ICMP`
+fn signed_operand(parts: &[Part]) -> bool {
+    match parts.first() {
+        Some(Part::OpCode(OpCode::IMUL | OpCode::IDIV | OpCode::IMOD)) => {
+            (2..4).contains(&parts.len())
+        }
+        Some(Part::OpCode(OpCode::ICMP)) => parts.len() == 2,
+        _ => false,
+    }
+}
+
+/// Part for a word, an error for words that are not part of the language
+/// `signed`: the word is a sign-extended immediate
+fn parse_word(s: &str, signed: bool) -> Result<Part, String> {
+    if let Some(part) = keyword(s) {
+        Ok(part)
+    } else if let Some(x) = s.strip_prefix('$').filter(|x| x.contains('.')) {
+        let parsed = x
+            .parse::<f64>()
+            .map_err(|_| format!("could not parse `{}` into a float", x))?;
+        Ok(Part::Float(parsed))
+    } else if Expr::is_operator(s) {
+        Err(format!(
+            "`{}` is an operator, an expression with spaces goes in parentheses, `(end - start)`",
+            s
+        ))
+    } else if let Some(value) = literal::parse(s) {
+        Ok(Part::Number(literal::operand(s, value?, signed)?))
+    } else {
+        Err(format!("unknown word `{s}`"))
+    }
+}
@@ -0,0 +1,40 @@
+use std::{env, fs};
+
+use novavm::object::{self, Object};
+
+fn main() -> Result<(), String> {
+    let args: Vec<_> = env::args().collect();
+
+    if args.len() < 2 {
+        return Err(format!(
+            "Usage {:?} [-o output.proj] [-l library]... input.obj...",
+            env::current_exe()
+        ));
+    }
+
+    let mut output: Option<&String> = None;
+    let mut objects: Vec<Object> = Vec::new();
+    let mut libraries: Vec<Object> = Vec::new();
+
+    let mut flags = args[1..].iter();
+    while let Some(f) = flags.next() {
+        match f.as_str() {
+            "-o" => output = Some(flags.next().ok_or("-o expects a file")?),
+            "-l" => libraries.extend(Object::load(flags.next().ok_or("-l expects a library")?)?),
+            _ => objects.extend(Object::load(f)?),
+        }
+    }
+
+    if objects.is_empty() {
+        return Err("no objects given".to_string());
+    }
+
+    let program = object::link(objects, libraries)?;
+    match output {
+        Some(path) => fs::write(path, program.to_string())
+            .map_err(|e| format!("could not write {}: {}", path, e))?,
+        None => print!("{}", program),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::io::{self, BufRead, Write};
+
+use novavm::{Machine, Register};
+
+const HELP: &str = "\
+| step [n]    : execute `n` instructions (default 1)
+| back [n]    : undo `n` instructions (default 1)
+| continue    : run until the machine halts
+| who REG     : run back to the last write of register REG
+| who @ADDR   : run back to the last write of memory address ADDR
+| goto N      : move to instruction count N
+| state       : print the machine state
+| quit        : stop debugging";
+
+/// Interactive time-travel debugger
+/// Reads commands from stdin and drives the machine forwards and backwards
+/// With `shared_stdin`, the guest reads the same stdin and commands go through its reader
+pub fn run(machine: &mut Machine, shared_stdin: bool) -> Result<(), String> {
+    machine.enable_history();
+    println!("{}", HELP);
+
+    loop {
+        print!("| #{} > ", machine.instruction_count());
+        io::stdout().flush().map_err(|e| e.to_string())?;
+
+        let Some(line) = read_command(machine, shared_stdin)? else {
+            return Ok(());
+        };
+
+        let words: Vec<_> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => continue,
+            ["step" | "s", rest @ ..] => repeat(rest, || {
+                if machine.halt {
+                    return Err("program has halted".to_string());
+                }
+                machine.step()
+            }),
+            ["back" | "b", rest @ ..] => repeat(rest, || machine.step_back()),
+            ["continue" | "c"] => {
+                let mut result = Ok(());
+                while !machine.halt && result.is_ok() {
+                    result = machine.step();
+                }
+                result
+            }
+            ["who" | "w", target] => who(machine, target),
+            ["goto" | "g", count] => parse_number(count).and_then(|c| machine.goto(c)),
+            ["state" | "p"] => {
+                machine.print_state();
+                Ok(())
+            }
+            ["quit" | "q"] => return Ok(()),
+            _ => Err(HELP.to_string()),
+        };
+
+        if let Err(e) = result {
+            println!("| {}", e);
+        }
+        if machine.halt {
+            println!("| halted");
+        }
+    }
+}
+
+/// Next command line, `None` at the end of input
+fn read_command(machine: &mut Machine, shared_stdin: bool) -> Result<Option<String>, String> {
+    if shared_stdin {
+        let line = machine.read_stdin_line()?;
+        return Ok(line.map(|l| String::from_utf8_lossy(&l).into_owned()));
+    }
+    let mut line = String::new();
+    let n = io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    Ok((n > 0).then_some(line))
+}
+
+/// Runs `f` as many times as the optional count in `rest` says
+fn repeat(rest: &[&str], mut f: impl FnMut() -> Result<(), String>) -> Result<(), String> {
+    let n = match rest {
+        [] => 1,
+        [n] => parse_number(n)?,
+        _ => return Err(HELP.to_string()),
+    };
+    for _ in 0..n {
+        f()?;
+    }
+    Ok(())
+}
+
+fn who(machine: &mut Machine, target: &str) -> Result<(), String> {
+    let count = if let Some(address) = target.strip_prefix('@') {
+        machine.run_back_to_memory_write(parse_number(address)? as usize)?
+    } else {
+        machine.run_back_to_register_write(Register::try_from(target)?)?
+    };
+    println!(
+        "| last written by instruction #{} at PC 0x{:04X}",
+        count,
+        machine.register(Register::PC)
+    );
+    Ok(())
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("could not parse `{}` as a number", s))
+}
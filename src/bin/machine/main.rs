@@ -1,140 +1,181 @@
-use std::{
-    env,
-    fs::File,
-    io::{self, BufRead},
-};
+use std::path::Path;
+use std::{env, fs, io};
 
+use novavm::clock::VirtualClock;
+use novavm::coverage;
+use novavm::program::Program;
 use novavm::Machine;
 
+mod debugger;
+
 fn main() -> Result<(), String> {
     let args: Vec<_> = env::args().collect();
 
     if args.len() < 2 {
-        return Err(format!("Usage {:?} [file].proj", env::current_exe()));
+        return Err(format!(
+            "Usage {:?} [--debug] [--debugger] [--no-fpu] [--heap-check] [--virtual-clock] [--seed n] [--stdin file] [--fs-root dir] [--env NAME[=VALUE]] [--save-state file] [--load-state file] [--steps n] [--profile file] [--coverage file] [file].proj [-- args...]",
+            env::current_exe()
+        ));
     }
 
-    let flags = if args.len() > 2 {
-        &args[1..args.len()]
-    } else {
-        &[]
-    };
-
-    let file_path = args.last().unwrap();
-
-    let (memory, data_section) = get_data(file_path)?;
-
     let mut machine = Machine::new();
-
-    machine.set_memory(&memory);
-    machine.set_data(&data_section);
-
-    flags.iter().for_each(|f| match f.as_str() {
-        "--debug" | "-d" => machine.enable_debug(),
-        _ => {}
-    });
-
-    println!("| RUNNING THE MACHINE |");
-
-    while !machine.halt {
-        machine.step()?;
+    let mut file_path: Option<&String> = None;
+    let mut save_state: Option<&String> = None;
+    let mut load_state: Option<&String> = None;
+    let mut max_steps: Option<u64> = None;
+    let mut debugger = false;
+    let mut stdin_file = false;
+    let mut profile: Option<&String> = None;
+    let mut coverage: Option<&String> = None;
+    let mut guest_args: Vec<String> = Vec::new();
+    let mut guest_env: Vec<String> = Vec::new();
+
+    let mut flags = args[1..].iter();
+    while let Some(f) = flags.next() {
+        match f.as_str() {
+            "--debug" | "-d" => machine.enable_debug(),
+            "--debugger" => debugger = true,
+            "--no-fpu" => machine.disable_fpu(),
+            "--heap-check" => machine.enable_heap_check(),
+            "--virtual-clock" => machine.set_clock(Box::new(VirtualClock::new())),
+            "--fs-root" => {
+                let root = flags.next().ok_or("--fs-root expects a directory")?;
+                machine.set_fs_root(Path::new(root))?;
+            }
+            "--env" => {
+                let var = flags.next().ok_or("--env expects a variable name")?;
+                // A bare name is passed through from the host environment
+                if var.contains('=') {
+                    guest_env.push(var.clone());
+                } else if let Ok(value) = env::var(var) {
+                    guest_env.push(format!("{}={}", var, value));
+                }
+            }
+            "--" => {
+                guest_args.extend(flags.by_ref().cloned());
+            }
+            "--seed" => {
+                let seed = flags.next().ok_or("--seed expects a number")?;
+                machine.seed_random(
+                    seed.parse()
+                        .map_err(|_| format!("could not parse seed `{}`", seed))?,
+                );
+            }
+            "--stdin" => {
+                let path = flags.next().ok_or("--stdin expects a file")?;
+                let file =
+                    fs::File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?;
+                machine.set_stdin(Box::new(io::BufReader::new(file)));
+                stdin_file = true;
+            }
+            "--profile" => {
+                profile = Some(flags.next().ok_or("--profile expects a file")?);
+                machine.enable_profiler();
+            }
+            "--coverage" => {
+                coverage = Some(flags.next().ok_or("--coverage expects a file")?);
+                machine.enable_coverage();
+            }
+            "--save-state" => {
+                save_state = Some(flags.next().ok_or("--save-state expects a file")?);
+            }
+            "--load-state" => {
+                load_state = Some(flags.next().ok_or("--load-state expects a file")?);
+            }
+            "--steps" => {
+                let steps = flags.next().ok_or("--steps expects a count")?;
+                max_steps = Some(
+                    steps
+                        .parse()
+                        .map_err(|_| format!("could not parse step count `{}`", steps))?,
+                );
+            }
+            _ => file_path = Some(f),
+        }
     }
 
-    Ok(())
-}
-
-fn get_data(file_path: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
-    let file = match File::open(file_path) {
-        Ok(f) => f,
-        Err(_) => return Err(format!("could not read {}", file_path)),
+    // With a snapshot, the program file is optional and only provides symbols
+    let program = match file_path {
+        Some(file_path) => Program::load(file_path)?,
+        None if load_state.is_some() => Program::default(),
+        None => return Err("no program given".to_string()),
     };
 
-    let reader = io::BufReader::new(file);
-
-    let mut memory: Vec<u8> = vec![];
-    let mut data: Vec<u8> = vec![];
-    let mut in_data_section: bool = false;
-
-    for line in reader.lines() {
-        let line = line.map_err(|_| "could not read line".to_string())?;
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-
-        if line == "[[DATA]]" {
-            in_data_section = true;
-            continue;
+    match load_state {
+        Some(state_path) => {
+            let state = fs::read(state_path)
+                .map_err(|e| format!("could not read {}: {}", state_path, e))?;
+            machine.restore(&state)?;
         }
-
-        let chunks: Vec<_> = line.split("0x").collect();
-
-        dbg!(&chunks);
-
-        for chunk in chunks {
-            if chunk.is_empty() {
-                continue;
+        None => {
+            machine.load(&program);
+            if let Some(file_path) = file_path {
+                guest_args.insert(0, file_path.clone());
             }
+            machine.set_args(&guest_args, &guest_env)?;
+        }
+    }
 
-            let value = u16::from_str_radix(chunk, 16)
-                .map_err(|_| format!("could not parse hex number from `{}`", chunk))?;
+    println!("| RUNNING THE MACHINE |");
 
-            let low = (value & 0xff) as u8;
+    let result = if debugger {
+        debugger::run(&mut machine, !stdin_file)
+    } else {
+        run(&mut machine, max_steps)
+    };
 
-            if !in_data_section {
-                memory.push(low);
-            } else {
-                data.push(low);
+    if machine.halt && machine.heap().checked {
+        let leaks: Vec<_> = machine.heap().leaks().collect();
+        if !leaks.is_empty() {
+            eprintln!("| HEAP: {} blocks leaked", leaks.len());
+            for (address, size) in leaks {
+                eprintln!("| 0x{:04X} {} bytes", address, size);
             }
         }
     }
 
-    Ok((memory, data))
-}
-
-fn _get_dataet_data(file_path: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
-    let file = match File::open(file_path) {
-        Ok(f) => f,
-        Err(_) => return Err(format!("could not read {}", file_path)),
+    // A snapshot that cannot be saved is reported after the run's own error, not instead of it
+    let result = match save_state.map(|path| save(&machine, path)) {
+        Some(Err(e)) => Err(match result {
+            Ok(()) => e,
+            Err(run) => format!("{}\n{}", run, e),
+        }),
+        _ => result,
     };
 
-    let reader = io::BufReader::new(file);
-    let mut memory = Vec::new();
-    let mut data = Vec::new();
-    let mut in_data_section = false;
+    if let (Some(profile_path), Some(profiler)) = (profile, machine.profiler()) {
+        eprint!("{}", profiler.report(&program.symbols));
+        fs::write(profile_path, profiler.folded(&program.symbols))
+            .map_err(|e| format!("could not write {}: {}", profile_path, e))?;
+    }
 
-    for line in reader.lines() {
-        let line = line.map_err(|_| "could not read line".to_string())?;
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
+    if let (Some(coverage_path), Some(recorded)) = (coverage, machine.coverage()) {
+        let mut hits = recorded.line_hits(&program.lines);
+        // Merge with the coverage of previous runs
+        if let Ok(previous) = fs::read_to_string(coverage_path) {
+            coverage::merge_lcov(&mut hits, &previous)?;
         }
+        fs::write(coverage_path, coverage::lcov(&hits))
+            .map_err(|e| format!("could not write {}: {}", coverage_path, e))?;
+    }
 
-        if line == "[[DATA]]" {
-            in_data_section = true;
-            continue;
-        }
+    result
+}
 
-        if line.starts_with("0x") {
-            let value = u16::from_str_radix(&line[2..], 16)
-                .map_err(|_| format!("could not parse hex number: {}", line))?;
-            let high_byte = (value >> 8) as u8; // Extract the high 8 bits
-            let low_byte = (value & 0xFF) as u8; // Extract the low 8 bits
+/// Writes a snapshot of the machine to `path`
+fn save(machine: &Machine, path: &str) -> Result<(), String> {
+    let snapshot = machine
+        .snapshot()
+        .map_err(|e| format!("could not save state: {}", e))?;
+    fs::write(path, snapshot).map_err(|e| format!("could not write {}: {}", path, e))
+}
 
-            if in_data_section {
-                if high_byte != 0x00 {
-                    data.push(high_byte);
-                }
-                data.push(low_byte);
-            } else {
-                if high_byte != 0x00 {
-                    memory.push(high_byte);
-                }
-                memory.push(low_byte);
-            }
-        } else {
-            return Err(format!("invalid format: {}", line));
-        }
+/// Runs the machine until it halts, or until `max_steps` instructions have been executed
+fn run(machine: &mut Machine, max_steps: Option<u64>) -> Result<(), String> {
+    let mut steps = 0;
+    while !machine.halt && max_steps.is_none_or(|max| steps < max) {
+        machine.step()?;
+        steps += 1;
     }
-
-    Ok((memory, data))
+    Ok(())
 }
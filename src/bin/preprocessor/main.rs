@@ -1,63 +1,55 @@
-#![allow(unused)]
-use pp::PreProcessor;
-use std::env;
-use std::fs::File;
-use std::io::{self, BufRead};
-use std::path::Path;
-
-mod pp;
-
-/// TODO:
-/// Implement a macrosystem
-/// ```asm
-/// !define_macro FOO 0x71 ; Set FOO as 0x71
-/// !define_macro BAR 0x09 ; Set BAR as 0x09
-///
-/// ; Multiline macro
-/// !define_macro BAZ => \
-///     PUSH !FOO        \
-///     POP   A          \
-///     EQ    A !FOO
-///
-/// ADD !FOO !BAR ;
-/// ```
-/// - Better comments
-/// ```asm
-/// ;;
-///     Multiline comment
-/// ;;
-///```
-pub struct TODO;
+use novavm::asm::{self, Options};
+use std::path::PathBuf;
+use std::{env, fs};
 
 fn main() -> Result<(), String> {
     let args: Vec<_> = env::args().collect();
 
     if args.len() < 2 {
         return Err(format!(
-            "Usage {} input.asm > output.proj",
+            "Usage {} [-I dir]... [-D NAME[=value]]... [-c] [-o output.proj|obj] input.asm",
             env::current_exe().unwrap().display()
         ));
     }
 
-    let file_path = args.get(1).unwrap();
-
-    let lines: Vec<_> = match read_lines(file_path) {
-        Ok(l) => l.flatten().collect(),
-        Err(e) => return Err(format!("{:?}", e)),
-    };
-
-    let mut pp = PreProcessor::new(lines);
+    let mut file_path: Option<&String> = None;
+    let mut output: Option<&String> = None;
+    let mut options = Options::default();
+    let mut object = false;
+    let mut flags = args[1..].iter();
+    while let Some(f) = flags.next() {
+        match f.as_str() {
+            "-I" => options
+                .include_paths
+                .push(PathBuf::from(flags.next().ok_or("-I expects a directory")?)),
+            "-D" => {
+                let define = flags.next().ok_or("-D expects NAME[=value]")?;
+                let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+                options.defines.push((name.to_string(), value.to_string()));
+            }
+            "-c" => object = true,
+            "-o" => output = Some(flags.next().ok_or("-o expects a file")?),
+            _ => file_path = Some(f),
+        }
+    }
+    let file_path = file_path.ok_or("no input file given")?;
+    options.file = file_path.clone();
+
+    let source = fs::read_to_string(file_path)
+        .map_err(|e| format!("could not read {}: {}", file_path, e))?;
+    let assembled = if object {
+        asm::assemble_object(&source, &options).map(|o| o.to_string())
+    } else {
+        asm::assemble(&source, &options).map(|p| p.to_string())
+    }
+    .map_err(|d| d.to_string())?;
 
-    pp.parse()?;
-    pp.print();
+    match output {
+        Some(path) => {
+            fs::write(path, assembled).map_err(|e| format!("could not write {}: {}", path, e))?
+        }
+        None => print!("{}", assembled),
+    }
 
     Ok(())
 }
-
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
-}
@@ -0,0 +1,332 @@
+use crate::flags::Flag;
+use crate::opcode::OpCode;
+use crate::{Machine, Register};
+
+/// Bitwise, shift and bit test instructions
+///
+/// Operands
+/// - Immediate form: `OP dst src imm`, e.g. `AND A B $15` puts `B & 15` into `A`
+/// - Register form : `OPR dst src1 src2`, e.g. `ANDR A B C` puts `B & C` into `A`
+/// - `NOT dst src` only has a register form
+/// - Bit instructions work in place: `BIT reg bit`, `BSET reg bit`, `BCLR reg bit`
+///
+/// FLAGS
+/// - Logic : Z and S from the result, C and O cleared
+/// - Shifts: Z and S from the result, C is the last bit shifted out, O cleared
+/// - Bits  : Z is set if the bit was clear before the instruction, other flags are kept
+impl Machine {
+    /// AND, OR, XOR and their register forms
+    pub(crate) fn handle_logic(&mut self, op: OpCode) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let src: Register = self.fetch()?;
+        let a = self.register(src);
+        let b = self.fetch_operand(matches!(op, OpCode::ANDR | OpCode::ORR | OpCode::XORR))?;
+        let result = match op {
+            OpCode::AND | OpCode::ANDR => a & b,
+            OpCode::OR | OpCode::ORR => a | b,
+            OpCode::XOR | OpCode::XORR => a ^ b,
+            _ => unreachable!("{:?} is not a logic opcode", op),
+        };
+        self.write_register(r, result);
+        self.set_flags(result, false, false);
+        if self.debug {
+            println!(
+                "| {:?}: Reg {:?} 0x{:X}, 0x{:X} -> 0x{:X}",
+                op, r, a, b, result
+            );
+        }
+        Ok(())
+    }
+
+    /// Put the complement of `src` into `dst`
+    pub(crate) fn handle_not(&mut self) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let src: Register = self.fetch()?;
+        let a = self.register(src);
+        let result = !a;
+        self.write_register(r, result);
+        self.set_flags(result, false, false);
+        if self.debug {
+            println!("| NOT: Reg {:?} 0x{:X} -> 0x{:X}", r, a, result);
+        }
+        Ok(())
+    }
+
+    /// SHL, SHR, SAR, ROL, ROR and their register forms
+    /// Shifting by 16 or more shifts every bit out, rotating is done modulo 16
+    pub(crate) fn handle_shift(&mut self, op: OpCode) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let src: Register = self.fetch()?;
+        let a = self.register(src);
+        let register_form = matches!(
+            op,
+            OpCode::SHLR | OpCode::SHRR | OpCode::SARR | OpCode::ROLR | OpCode::RORR
+        );
+        let count = self.fetch_operand(register_form)?;
+        // Past 16 every bit is shifted out, 17 keeps the carry clear
+        let n = count.min(17) as u32;
+        let turns = (count % 16) as u32;
+
+        let (result, carry) = match op {
+            OpCode::SHL | OpCode::SHLR => {
+                let wide = (a as u32) << n;
+                (wide as u16, wide & 0x1_0000 != 0)
+            }
+            OpCode::SHR | OpCode::SHRR => {
+                let wide = ((a as u32) << 1) >> n;
+                ((wide >> 1) as u16, wide & 1 != 0)
+            }
+            OpCode::SAR | OpCode::SARR => {
+                let wide = ((a as i16 as i32) << 1) >> n;
+                ((wide >> 1) as u16, wide & 1 != 0)
+            }
+            OpCode::ROL | OpCode::ROLR => {
+                let result = a.rotate_left(turns);
+                (result, count != 0 && result & 1 != 0)
+            }
+            OpCode::ROR | OpCode::RORR => {
+                let result = a.rotate_right(turns);
+                (result, count != 0 && result & 0x8000 != 0)
+            }
+            _ => unreachable!("{:?} is not a shift opcode", op),
+        };
+        self.write_register(r, result);
+        self.set_flags(result, carry, false);
+        if self.debug {
+            println!(
+                "| {:?}: Reg {:?} 0x{:X}, {} -> 0x{:X}",
+                op, r, a, count, result
+            );
+        }
+        Ok(())
+    }
+
+    /// BIT, BSET, BCLR and their register forms
+    /// The bit index is taken modulo 16
+    pub(crate) fn handle_bit(&mut self, op: OpCode) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let register_form = matches!(op, OpCode::BITR | OpCode::BSETR | OpCode::BCLRR);
+        let bit = self.fetch_operand(register_form)? % 16;
+        let mask = 1u16 << bit;
+        let a = self.register(r);
+
+        match op {
+            OpCode::BIT | OpCode::BITR => {}
+            OpCode::BSET | OpCode::BSETR => self.write_register(r, a | mask),
+            OpCode::BCLR | OpCode::BCLRR => self.write_register(r, a & !mask),
+            _ => unreachable!("{:?} is not a bit opcode", op),
+        }
+        self.set_flag(Flag::Zero, a & mask == 0);
+        if self.debug {
+            println!("| {:?}: Reg {:?} 0x{:X}, bit {}", op, r, a, bit);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: u8 = Register::A as u8;
+    const B: u8 = Register::B as u8;
+    const C: u8 = Register::C as u8;
+
+    /// Runs a single instruction with `B` and `C` set and every flag set beforehand
+    fn run(instruction: &[u8], b: u16, c: u16) -> Machine {
+        let mut m = Machine::new();
+        m.set_memory(instruction);
+        m.registers[Register::B as usize] = b;
+        m.registers[Register::C as usize] = c;
+        m.registers[Register::FLAGS as usize] = 0xF;
+        m.step().unwrap();
+        m
+    }
+
+    /// `OP A B imm`, returns `A`
+    fn imm(op: OpCode, b: u16, v: u8) -> Machine {
+        run(&[op as u8, A, B, v], b, 0)
+    }
+
+    /// `OPR A B C`
+    fn reg(op: OpCode, b: u16, c: u16) -> Machine {
+        run(&[op as u8, A, B, C], b, c)
+    }
+
+    /// Z, C, S, O
+    fn flags(m: &Machine) -> [bool; 4] {
+        [
+            m.flag(Flag::Zero),
+            m.flag(Flag::Carry),
+            m.flag(Flag::Sign),
+            m.flag(Flag::Overflow),
+        ]
+    }
+
+    fn a(m: &Machine) -> u16 {
+        m.register(Register::A)
+    }
+
+    #[test]
+    fn logic() {
+        let m = imm(OpCode::AND, 0x00F3, 0x0F);
+        assert_eq!((a(&m), flags(&m)), (0x0003, [false, false, false, false]));
+        let m = imm(OpCode::AND, 0x00F0, 0x0F);
+        assert_eq!((a(&m), flags(&m)), (0, [true, false, false, false]));
+        let m = imm(OpCode::OR, 0x8000, 0x01);
+        assert_eq!((a(&m), flags(&m)), (0x8001, [false, false, true, false]));
+        let m = imm(OpCode::XOR, 0x00FF, 0xFF);
+        assert_eq!((a(&m), flags(&m)), (0, [true, false, false, false]));
+
+        let m = reg(OpCode::ANDR, 0xFF00, 0x0FF0);
+        assert_eq!((a(&m), flags(&m)), (0x0F00, [false, false, false, false]));
+        let m = reg(OpCode::ORR, 0x8000, 0x0001);
+        assert_eq!((a(&m), flags(&m)), (0x8001, [false, false, true, false]));
+        let m = reg(OpCode::XORR, 0x1234, 0x1234);
+        assert_eq!((a(&m), flags(&m)), (0, [true, false, false, false]));
+    }
+
+    #[test]
+    fn not() {
+        let m = run(&[OpCode::NOT as u8, A, B], 0x00FF, 0);
+        assert_eq!((a(&m), flags(&m)), (0xFF00, [false, false, true, false]));
+        let m = run(&[OpCode::NOT as u8, A, B], 0xFFFF, 0);
+        assert_eq!((a(&m), flags(&m)), (0, [true, false, false, false]));
+    }
+
+    #[test]
+    fn shift_by_zero() {
+        for op in [
+            OpCode::SHL,
+            OpCode::SHR,
+            OpCode::SAR,
+            OpCode::ROL,
+            OpCode::ROR,
+        ] {
+            let m = imm(op, 0x8001, 0);
+            assert_eq!(
+                (a(&m), flags(&m)),
+                (0x8001, [false, false, true, false]),
+                "{op:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn shl() {
+        let m = imm(OpCode::SHL, 0x8001, 1);
+        assert_eq!((a(&m), flags(&m)), (0x0002, [false, true, false, false]));
+        let m = imm(OpCode::SHL, 0x4000, 1);
+        assert_eq!((a(&m), flags(&m)), (0x8000, [false, false, true, false]));
+        let m = imm(OpCode::SHL, 0x0001, 16);
+        assert_eq!((a(&m), flags(&m)), (0, [true, true, false, false]));
+        let m = imm(OpCode::SHL, 0xFFFF, 20);
+        assert_eq!((a(&m), flags(&m)), (0, [true, false, false, false]));
+        let m = reg(OpCode::SHLR, 0x0003, 300);
+        assert_eq!((a(&m), flags(&m)), (0, [true, false, false, false]));
+    }
+
+    #[test]
+    fn shr() {
+        let m = imm(OpCode::SHR, 0x0003, 1);
+        assert_eq!((a(&m), flags(&m)), (0x0001, [false, true, false, false]));
+        let m = imm(OpCode::SHR, 0x8000, 15);
+        assert_eq!((a(&m), flags(&m)), (0x0001, [false, false, false, false]));
+        let m = imm(OpCode::SHR, 0x8000, 16);
+        assert_eq!((a(&m), flags(&m)), (0, [true, true, false, false]));
+        let m = reg(OpCode::SHRR, 0xFFFF, 17);
+        assert_eq!((a(&m), flags(&m)), (0, [true, false, false, false]));
+    }
+
+    #[test]
+    fn sar() {
+        let m = imm(OpCode::SAR, 0x8000, 1);
+        assert_eq!((a(&m), flags(&m)), (0xC000, [false, false, true, false]));
+        let m = imm(OpCode::SAR, 0x8008, 4);
+        assert_eq!((a(&m), flags(&m)), (0xF800, [false, true, true, false]));
+        let m = imm(OpCode::SAR, 0xFFF0, 20);
+        assert_eq!((a(&m), flags(&m)), (0xFFFF, [false, true, true, false]));
+        let m = imm(OpCode::SAR, 0x7FFF, 16);
+        assert_eq!((a(&m), flags(&m)), (0, [true, false, false, false]));
+        let m = reg(OpCode::SARR, 0x8000, 16);
+        assert_eq!((a(&m), flags(&m)), (0xFFFF, [false, true, true, false]));
+    }
+
+    #[test]
+    fn rol() {
+        let m = imm(OpCode::ROL, 0x8000, 1);
+        assert_eq!((a(&m), flags(&m)), (0x0001, [false, true, false, false]));
+        let m = imm(OpCode::ROL, 0x0001, 20);
+        assert_eq!((a(&m), flags(&m)), (0x0010, [false, false, false, false]));
+        let m = imm(OpCode::ROL, 0x8001, 16);
+        assert_eq!((a(&m), flags(&m)), (0x8001, [false, true, true, false]));
+        let m = reg(OpCode::ROLR, 0x0001, 20);
+        assert_eq!((a(&m), flags(&m)), (0x0010, [false, false, false, false]));
+        let m = reg(OpCode::ROLR, 0x0001, 33);
+        assert_eq!((a(&m), flags(&m)), (0x0002, [false, false, false, false]));
+    }
+
+    #[test]
+    fn ror() {
+        let m = imm(OpCode::ROR, 0x0001, 1);
+        assert_eq!((a(&m), flags(&m)), (0x8000, [false, true, true, false]));
+        let m = imm(OpCode::ROR, 0x0001, 17);
+        assert_eq!((a(&m), flags(&m)), (0x8000, [false, true, true, false]));
+        let m = imm(OpCode::ROR, 0x0010, 20);
+        assert_eq!((a(&m), flags(&m)), (0x0001, [false, false, false, false]));
+        let m = reg(OpCode::RORR, 0x0100, 24);
+        assert_eq!((a(&m), flags(&m)), (0x0001, [false, false, false, false]));
+    }
+
+    /// `OP B bit` with `B` set and every flag set beforehand
+    fn bit(op: OpCode, b: u16, bit: u8) -> Machine {
+        run(&[op as u8, B, bit], b, 0)
+    }
+
+    #[test]
+    fn bit_test() {
+        let m = bit(OpCode::BIT, 0x0002, 1);
+        assert_eq!(
+            (m.register(Register::B), flags(&m)),
+            (0x0002, [false, true, true, true])
+        );
+        let m = bit(OpCode::BIT, 0x0002, 0);
+        assert_eq!(
+            (m.register(Register::B), flags(&m)),
+            (0x0002, [true, true, true, true])
+        );
+        // The index is taken modulo 16
+        let m = bit(OpCode::BIT, 0x0002, 17);
+        assert!(!m.flag(Flag::Zero));
+        let m = run(&[OpCode::BITR as u8, B, C], 0x8000, 31);
+        assert!(!m.flag(Flag::Zero));
+    }
+
+    #[test]
+    fn bit_set_and_clear() {
+        let m = bit(OpCode::BSET, 0x0000, 3);
+        assert_eq!(
+            (m.register(Register::B), m.flag(Flag::Zero)),
+            (0x0008, true)
+        );
+        let m = bit(OpCode::BSET, 0x0008, 19);
+        assert_eq!(
+            (m.register(Register::B), m.flag(Flag::Zero)),
+            (0x0008, false)
+        );
+        let m = bit(OpCode::BCLR, 0xFFFF, 16);
+        assert_eq!(
+            (m.register(Register::B), m.flag(Flag::Zero)),
+            (0xFFFE, false)
+        );
+        let m = bit(OpCode::BCLR, 0x0000, 2);
+        assert_eq!(
+            (m.register(Register::B), m.flag(Flag::Zero)),
+            (0x0000, true)
+        );
+        let m = run(&[OpCode::BSETR as u8, B, C], 0x0000, 0x0020);
+        assert_eq!(m.register(Register::B), 0x0001);
+        let m = run(&[OpCode::BCLRR as u8, B, C], 0x8000, 0x002F);
+        assert_eq!(m.register(Register::B), 0x0000);
+    }
+}
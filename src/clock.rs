@@ -0,0 +1,199 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{Machine, Register};
+
+/// Source of time for the time syscalls
+/// Every method gets how many instructions have been executed, for clocks that follow the program
+pub trait Clock {
+    /// Milliseconds since the Unix epoch
+    fn wall(&mut self, instructions: u64) -> u64;
+    /// Milliseconds since an arbitrary start, never goes backwards
+    fn monotonic(&mut self, instructions: u64) -> u64;
+    /// Waits for `ms` milliseconds
+    fn sleep(&mut self, ms: u64);
+    /// State kept in snapshots, `None` for clocks that follow the host
+    fn save(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Restores a state made by `save`, left untouched on error
+    fn load(&mut self, _state: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The host's clocks, sleeping blocks the machine
+#[derive(Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn wall(&mut self, _instructions: u64) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
+
+    fn monotonic(&mut self, _instructions: u64) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn sleep(&mut self, ms: u64) {
+        std::thread::sleep(Duration::from_millis(ms));
+    }
+}
+
+/// Deterministic clock, time only moves with executed instructions and sleeps
+/// - Start          : Wall clock time at instruction 0, in milliseconds since the Unix epoch
+/// - Per instruction: Nanoseconds every instruction takes
+/// - Slept          : Milliseconds skipped by sleeping, sleeping returns immediately
+#[derive(Debug, Default)]
+pub struct VirtualClock {
+    pub start: u64,
+    pub per_instruction: u64,
+    slept: u64,
+}
+
+impl VirtualClock {
+    /// Default nanoseconds per instruction
+    pub const PER_INSTRUCTION: u64 = 1000;
+
+    pub fn new() -> Self {
+        Self {
+            start: 0,
+            per_instruction: Self::PER_INSTRUCTION,
+            slept: 0,
+        }
+    }
+}
+
+impl Clock for VirtualClock {
+    fn wall(&mut self, instructions: u64) -> u64 {
+        self.start + self.monotonic(instructions)
+    }
+
+    fn monotonic(&mut self, instructions: u64) -> u64 {
+        instructions * self.per_instruction / 1_000_000 + self.slept
+    }
+
+    fn sleep(&mut self, ms: u64) {
+        self.slept += ms;
+    }
+
+    fn save(&self) -> Option<Vec<u8>> {
+        let fields = [self.start, self.per_instruction, self.slept];
+        Some(fields.iter().flat_map(|f| f.to_le_bytes()).collect())
+    }
+
+    fn load(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != 24 {
+            return Err("invalid clock section".to_string());
+        }
+        let field = |i: usize| u64::from_le_bytes(state[i * 8..i * 8 + 8].try_into().unwrap());
+        self.start = field(0);
+        self.per_instruction = field(1);
+        self.slept = field(2);
+        Ok(())
+    }
+}
+
+/// Time syscalls, every operand is a register
+/// - `TIME hi lo`     : seconds since the Unix epoch, as a 32-bit value in `hi:lo`
+/// - `MONOTONIC hi lo`: milliseconds since the machine started, as a 32-bit value in `hi:lo`, wraps after 49 days
+/// - `SLEEP ms`       : waits for `ms` milliseconds
+///
+/// The state of clocks that do not follow the host, like [`VirtualClock`], is kept in snapshots
+impl Machine {
+    /// Replaces the clock used by the time syscalls
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Writes a 32-bit value into the register pair `hi:lo`
+    fn write_pair(&mut self, hi: Register, lo: Register, v: u32) {
+        self.write_register(hi, (v >> 16) as u16);
+        self.write_register(lo, v as u16);
+    }
+
+    pub(crate) fn syscall_time(&mut self) -> Result<(), String> {
+        let hi: Register = self.fetch()?;
+        let lo: Register = self.fetch()?;
+        let seconds = self.clock.wall(self.instruction_count) / 1000;
+        self.write_pair(hi, lo, seconds as u32);
+        if self.debug {
+            println!("| TIME: {}", seconds);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn syscall_monotonic(&mut self) -> Result<(), String> {
+        let hi: Register = self.fetch()?;
+        let lo: Register = self.fetch()?;
+        let ms = self.clock.monotonic(self.instruction_count);
+        self.write_pair(hi, lo, ms as u32);
+        if self.debug {
+            println!("| MONOTONIC: {}", ms);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn syscall_sleep(&mut self) -> Result<(), String> {
+        let ms: Register = self.fetch()?;
+        let ms = self.register(ms) as u64;
+        self.clock.sleep(ms);
+        if self.debug {
+            println!("| SLEEP: {} ms", ms);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{machine, run};
+
+    #[test]
+    fn virtual_time() {
+        let mut m = machine(
+            "SYSCALL MONOTONIC A B\nADD C $250 $0\nSYSCALL SLEEP C\n\
+             SYSCALL MONOTONIC R4 R5\nSYSCALL TIME R6 R7\nHALT",
+        );
+        // One millisecond per instruction, counting the one running
+        m.set_clock(Box::new(VirtualClock {
+            start: 100_000_000_000,
+            per_instruction: 1_000_000,
+            ..VirtualClock::new()
+        }));
+        run(&mut m).unwrap();
+        let pair = |hi, lo| (m.register(hi) as u32) << 16 | m.register(lo) as u32;
+        assert_eq!(pair(Register::A, Register::B), 1);
+        assert_eq!(pair(Register::R4, Register::R5), 4 + 250);
+        assert_eq!(pair(Register::R6, Register::R7), 100_000_000);
+    }
+
+    #[test]
+    fn saved_state() {
+        let mut clock = VirtualClock::new();
+        clock.sleep(20);
+        let mut restored = VirtualClock::new();
+        restored.load(&clock.save().unwrap()).unwrap();
+        assert_eq!(restored.monotonic(3000), 23);
+        assert_eq!(restored.load(&[0; 8]).unwrap_err(), "invalid clock section");
+        assert!(SystemClock::new().save().is_none());
+    }
+}
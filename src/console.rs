@@ -0,0 +1,221 @@
+use std::io::{self, BufRead, Write};
+
+use crate::flags::Flag;
+use crate::{Machine, Register};
+
+/// Writes raw bytes to stdout, the guest's text is not required to be UTF-8
+fn print_bytes(bytes: &[u8]) -> Result<(), String> {
+    io::stdout().write_all(bytes).map_err(|e| e.to_string())
+}
+
+/// `v` as printed by `PRINT_INT` in `mode`
+fn format_int(v: u16, mode: u8) -> Result<String, String> {
+    Ok(match mode {
+        0 => v.to_string(),
+        1 => (v as i16).to_string(),
+        2 => format!("{:X}", v),
+        3 => format!("{:b}", v),
+        _ => return Err(format!("PRINT_INT: unsupported mode {}", mode)),
+    })
+}
+
+/// Console syscalls, printed to stdout and read from the machine's stdin
+/// - `PRINT_INT mode reg`: the value of `reg`, the immediate `mode` is 0 for unsigned decimal,
+///   1 for signed decimal, 2 for hexadecimal and 3 for binary
+/// - `PRINT_CHAR reg`    : the low byte of `reg`
+/// - `PRINT_STR reg`     : the null terminated string at the address in `reg`, in memory
+/// - `READ_LINE dst buf len`: the next line into `buf`, null terminated and without its newline,
+///   `dst` is its length, what does not fit in `len` bytes is dropped
+/// - `READ_INT dst`      : the next line as a signed or unsigned decimal number
+///
+/// FLAGS
+/// - C: end of input, nothing was read and `dst` is 0
+/// - O: `READ_INT` did not get a number, `dst` is 0
+impl Machine {
+    /// Replaces the input read by `READ` on descriptor 0, `READ_LINE` and `READ_INT`
+    pub fn set_stdin(&mut self, stdin: Box<dyn BufRead>) {
+        self.stdin = stdin;
+    }
+
+    /// Reads up to `len` bytes from stdin
+    pub(crate) fn read_stdin(&mut self, len: usize) -> Result<Vec<u8>, String> {
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        let available = self.stdin.fill_buf().map_err(|e| e.to_string())?;
+        let bytes = available[..len.min(available.len())].to_vec();
+        self.stdin.consume(bytes.len());
+        Ok(bytes)
+    }
+
+    /// Next line of stdin without its line ending, `None` at the end of input
+    /// Hosts sharing stdin with the guest, like a debugger, read through here so neither loses input
+    pub fn read_stdin_line(&mut self) -> Result<Option<Vec<u8>>, String> {
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        let mut line = Vec::new();
+        let n = self
+            .stdin
+            .read_until(b'\n', &mut line)
+            .map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Ok(None);
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    pub(crate) fn syscall_print_int(&mut self) -> Result<(), String> {
+        let mode: u8 = self.fetch()?;
+        let r: Register = self.fetch()?;
+        let text = format_int(self.register(r), mode)?;
+        if self.debug {
+            println!("| PRINT_INT: Reg {:?} mode {}", r, mode);
+        }
+        print_bytes(text.as_bytes())
+    }
+
+    pub(crate) fn syscall_print_char(&mut self) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        if self.debug {
+            println!("| PRINT_CHAR: Reg {:?}", r);
+        }
+        print_bytes(&[self.register(r) as u8])
+    }
+
+    pub(crate) fn syscall_print_str(&mut self) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let address = self.register(r) as usize;
+        if self.debug {
+            println!("| PRINT_STR: 0x{:04X}", address);
+        }
+        print_bytes(self.read_cstr(address)?)
+    }
+
+    pub(crate) fn syscall_read_line(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [buf, len] = self.fetch_values()?;
+        if len == 0 {
+            return Err("READ_LINE: buffer has no room for the null".to_string());
+        }
+        let line = self.read_stdin_line()?;
+        let eof = line.is_none();
+        let mut bytes = line.unwrap_or_default();
+        bytes.truncate(len as usize - 1);
+        let n = bytes.len() as u16;
+        bytes.push(0);
+        self.write_memory(buf as usize, &bytes)?;
+        self.write_register(dst, n);
+        self.set_flag(Flag::Carry, eof);
+        if self.debug {
+            println!("| READ_LINE: Reg {:?} -> {}", dst, n);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn syscall_read_int(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let line = self.read_stdin_line()?;
+        let eof = line.is_none();
+        let value = line.and_then(|line| {
+            let text = String::from_utf8(line).ok()?;
+            let text = text.trim();
+            text.parse::<i16>()
+                .map(|v| v as u16)
+                .or_else(|_| text.parse::<u16>())
+                .ok()
+        });
+        self.write_register(dst, value.unwrap_or(0));
+        self.set_flag(Flag::Carry, eof);
+        self.set_flag(Flag::Overflow, !eof && value.is_none());
+        if self.debug {
+            println!("| READ_INT: Reg {:?} -> {:?}", dst, value);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{machine, run};
+
+    #[test]
+    fn int_formats() {
+        assert_eq!(format_int(0xFFF1, 0).unwrap(), "65521");
+        assert_eq!(format_int(0xFFF1, 1).unwrap(), "-15");
+        assert_eq!(format_int(0xFFF1, 2).unwrap(), "FFF1");
+        assert_eq!(format_int(5, 3).unwrap(), "101");
+        assert_eq!(
+            format_int(5, 4).unwrap_err(),
+            "PRINT_INT: unsupported mode 4"
+        );
+    }
+
+    #[test]
+    fn print_errors() {
+        let mut m = machine("SYSCALL PRINT_INT $4 A\nHALT");
+        assert_eq!(run(&mut m).unwrap_err(), "PRINT_INT: unsupported mode 4");
+        // No null up to the end of memory
+        let mut m = machine("SYSCALL PRINT_STR A\nHALT");
+        m.memory[0xFFE..].fill(b'x');
+        m.registers[Register::A as usize] = 0xFFE;
+        assert_eq!(
+            run(&mut m).unwrap_err(),
+            "no null terminated string at 0x0FFE"
+        );
+    }
+
+    fn with_stdin(source: &str, input: &str) -> Machine {
+        let mut m = machine(source);
+        m.set_stdin(Box::new(io::Cursor::new(input.as_bytes().to_vec())));
+        m
+    }
+
+    #[test]
+    fn read_lines() {
+        let mut m = with_stdin(
+            "SYSCALL READ_LINE A B C\nSYSCALL READ_LINE A B C\nHALT",
+            "hello world\r\n",
+        );
+        m.registers[Register::B as usize] = 0x100;
+        m.registers[Register::C as usize] = 6;
+        m.step().unwrap();
+        assert_eq!(m.register(Register::A), 5);
+        assert_eq!(&m.memory[0x100..0x106], b"hello\0");
+        assert!(!m.flag(Flag::Carry));
+        // The rest of the line was dropped, not left for the next read
+        m.step().unwrap();
+        assert_eq!(m.register(Register::A), 0);
+        assert!(m.flag(Flag::Carry));
+    }
+
+    #[test]
+    fn read_ints() {
+        let mut m = with_stdin(&"SYSCALL READ_INT A\n".repeat(4), " -12 \n65535\nabc\n");
+        let mut read = || {
+            m.step().unwrap();
+            let flags = [Flag::Carry, Flag::Overflow].map(|f| m.flag(f));
+            (m.register(Register::A), flags)
+        };
+        assert_eq!(read(), (-12i16 as u16, [false, false]));
+        assert_eq!(read(), (65535, [false, false]));
+        assert_eq!(read(), (0, [false, true]));
+        assert_eq!(read(), (0, [true, false]));
+    }
+
+    #[test]
+    fn read_stdin() {
+        let mut m = with_stdin("SYSCALL READ A R4 B C\nSYSCALL READ A R4 B C\nHALT", "ab");
+        m.registers[Register::B as usize] = 0x100;
+        m.registers[Register::C as usize] = 4;
+        m.step().unwrap();
+        assert_eq!(m.register(Register::A), 2);
+        assert_eq!(&m.memory[0x100..0x102], b"ab");
+        m.step().unwrap();
+        assert_eq!(m.register(Register::A), 0);
+        assert!(m.flag(Flag::Carry));
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::lines::LineTable;
+use crate::Machine;
+
+/// Records which instructions have been executed
+#[derive(Debug, Default)]
+pub struct Coverage {
+    hits: HashMap<u16, u64>,
+}
+
+/// Hit counts per line, per source file
+pub type LineHits = BTreeMap<String, BTreeMap<u32, u64>>;
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one execution of the instruction at `pc`
+    pub fn record(&mut self, pc: u16) {
+        *self.hits.entry(pc).or_default() += 1;
+    }
+
+    /// Maps the recorded hits back to source lines
+    /// Every line that holds an instruction is included, executed or not
+    pub fn line_hits(&self, lines: &LineTable) -> LineHits {
+        let mut hits = LineHits::new();
+        for (address, source) in lines.iter() {
+            *hits
+                .entry(source.file.clone())
+                .or_default()
+                .entry(source.line)
+                .or_default() += self.hits.get(&address).copied().unwrap_or(0);
+        }
+        hits
+    }
+}
+
+/// Adds the hits from an lcov tracefile to `hits`, used to merge coverage across runs
+pub fn merge_lcov(hits: &mut LineHits, lcov: &str) -> Result<(), String> {
+    let mut file: Option<String> = None;
+    for line in lcov.lines() {
+        if let Some(source) = line.strip_prefix("SF:") {
+            file = Some(source.to_string());
+        } else if let Some(da) = line.strip_prefix("DA:") {
+            let source = file.clone().ok_or("DA record outside of a source file")?;
+            let mut fields = da.split(',');
+            let (Some(line), Some(count)) = (fields.next(), fields.next()) else {
+                return Err(format!("invalid DA record `{}`", da));
+            };
+            let line: u32 = line
+                .parse()
+                .map_err(|_| format!("invalid line number in `{}`", da))?;
+            let count: u64 = count
+                .parse()
+                .map_err(|_| format!("invalid hit count in `{}`", da))?;
+            *hits.entry(source).or_default().entry(line).or_default() += count;
+        } else if line == "end_of_record" {
+            file = None;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `hits` as an lcov tracefile
+pub fn lcov(hits: &LineHits) -> String {
+    let mut out = String::new();
+    for (file, lines) in hits {
+        let _ = writeln!(out, "TN:");
+        let _ = writeln!(out, "SF:{}", file);
+        for (line, count) in lines {
+            let _ = writeln!(out, "DA:{},{}", line, count);
+        }
+        let _ = writeln!(out, "LF:{}", lines.len());
+        let _ = writeln!(out, "LH:{}", lines.values().filter(|&&c| c > 0).count());
+        let _ = writeln!(out, "end_of_record");
+    }
+    out
+}
+
+impl Machine {
+    /// Starts recording which instructions are executed
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    /// Recorded coverage, if coverage is enabled
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::{assemble, Options};
+    use crate::program::Program;
+    use crate::tests::run;
+
+    #[test]
+    fn line_hits() {
+        let options = Options {
+            file: "cov.asm".to_string(),
+            ..Options::default()
+        };
+        let program = assemble("ADD A $1 $0\n\nHALT\nADD B $1 $0", &options).unwrap();
+        // Source lines survive the text format
+        let program = Program::parse(&program.to_string()).unwrap();
+        let mut m = Machine::new();
+        m.load(&program);
+        m.enable_coverage();
+        run(&mut m).unwrap();
+
+        let hits = m.coverage().unwrap().line_hits(&program.lines);
+        let lines: Vec<_> = hits["cov.asm"].iter().map(|(&l, &c)| (l, c)).collect();
+        assert_eq!(lines, [(1, 1), (3, 1), (4, 0)]);
+        assert_eq!(
+            lcov(&hits),
+            "TN:\nSF:cov.asm\nDA:1,1\nDA:3,1\nDA:4,0\nLF:3\nLH:2\nend_of_record\n"
+        );
+    }
+
+    #[test]
+    fn merged_runs() {
+        let mut hits = LineHits::new();
+        let run = "SF:a.asm\nDA:1,1\nDA:2,0\nend_of_record\n";
+        merge_lcov(&mut hits, run).unwrap();
+        merge_lcov(&mut hits, &run.replace("DA:2,0", "DA:2,3")).unwrap();
+        assert_eq!(
+            lcov(&hits),
+            "TN:\nSF:a.asm\nDA:1,2\nDA:2,3\nLF:2\nLH:2\nend_of_record\n"
+        );
+        assert_eq!(
+            merge_lcov(&mut hits, "DA:1,1").unwrap_err(),
+            "DA record outside of a source file"
+        );
+        assert_eq!(
+            merge_lcov(&mut hits, "SF:a.asm\nDA:x,1").unwrap_err(),
+            "invalid line number in `x,1`"
+        );
+    }
+}
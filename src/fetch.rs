@@ -2,22 +2,38 @@ use crate::opcode::OpCode;
 use crate::syscall::Syscall;
 use crate::Register;
 
-pub trait Fetch<'a>: TryFrom<u8> {}
+pub trait Fetch<'a>: TryFrom<u8> {
+    /// Decodes a byte of a program assembled for the given bytecode version
+    fn decode(value: u8, _version: u8) -> Option<Self> {
+        Self::try_from(value).ok()
+    }
+}
 
-impl<'a> Fetch<'a> for Register {}
+impl<'a> Fetch<'a> for Register {
+    /// Version 1 programs only know `A`, `B`, `C`, `M`, `SP`, `PC` and `FLAGS`, encoded as 0..=6
+    fn decode(value: u8, version: u8) -> Option<Self> {
+        if version >= 2 {
+            return Self::try_from(value).ok();
+        }
+        match value {
+            0..=3 => Some(Register::GENERAL[value as usize]),
+            4 => Some(Self::SP),
+            5 => Some(Self::PC),
+            6 => Some(Self::FLAGS),
+            _ => None,
+        }
+    }
+}
 
 impl TryFrom<u8> for Register {
     type Error = String;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0 => Ok(Self::A),
-            1 => Ok(Self::B),
-            2 => Ok(Self::C),
-            3 => Ok(Self::M),
-            4 => Ok(Self::SP),
-            5 => Ok(Self::PC),
-            6 => Ok(Self::FLAGS),
+            0..=15 => Ok(Self::GENERAL[value as usize]),
+            16 => Ok(Self::SP),
+            17 => Ok(Self::PC),
+            18 => Ok(Self::FLAGS),
             _ => Err(format!("{} is not a valid register", value)),
         }
     }
@@ -34,7 +50,10 @@ impl TryFrom<&str> for Register {
             "SP" => Ok(Self::SP),
             "PC" => Ok(Self::PC),
             "FLAGS" => Ok(Self::FLAGS),
-            _ => Err(format!("{} is not a valid register", value)),
+            _ => (0..Self::GENERAL.len())
+                .find(|n| value == format!("R{}", n))
+                .map(|n| Self::GENERAL[n])
+                .ok_or(format!("{} is not a valid register", value)),
         }
     }
 }
@@ -45,4 +64,49 @@ impl<'a> Fetch<'a> for Syscall {}
 
 impl<'a> Fetch<'a> for u8 {}
 impl<'a> Fetch<'a> for u16 {}
-impl<'a> Fetch<'a> for usize {}
\ No newline at end of file
+impl<'a> Fetch<'a> for usize {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::tests::run;
+    use crate::Machine;
+
+    #[test]
+    fn register_names() {
+        assert_eq!(Register::try_from("A"), Ok(Register::R0));
+        assert_eq!(Register::try_from("M"), Ok(Register::R3));
+        assert_eq!(Register::try_from("R15"), Ok(Register::R15));
+        assert_eq!(Register::try_from("FLAGS"), Ok(Register::FLAGS));
+        assert!(Register::try_from("R16").is_err());
+        assert!(Register::try_from("r1").is_err());
+        assert_eq!(Register::try_from(15), Ok(Register::R15));
+        assert_eq!(Register::try_from(16), Ok(Register::SP));
+        assert!(Register::try_from(19).is_err());
+    }
+
+    #[test]
+    fn version_1_registers() {
+        assert_eq!(Register::decode(3, 1), Some(Register::M));
+        assert_eq!(Register::decode(4, 1), Some(Register::SP));
+        assert_eq!(Register::decode(6, 1), Some(Register::FLAGS));
+        assert_eq!(Register::decode(7, 1), None);
+        assert_eq!(Register::decode(4, 2), Some(Register::R4));
+
+        // Programs without a version header are version 1, where 4 is `SP`
+        let add = [OpCode::ADD as u8, 4, 2, 3, OpCode::HALT as u8];
+        let program = Program {
+            memory: add.to_vec(),
+            ..Program::default()
+        };
+        let text = program.to_string().replace("[[VERSION 2]]", "");
+        let program = Program::parse(&text).unwrap();
+        assert_eq!(program.version, 1);
+        let mut m = Machine::new();
+        m.load(&program);
+        run(&mut m).unwrap();
+        assert_eq!(m.register(Register::SP), 5);
+        assert_eq!(m.register(Register::R4), 0);
+    }
+}
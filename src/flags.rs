@@ -0,0 +1,51 @@
+use crate::{Machine, Register};
+
+/// Bits of the FLAGS register
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[repr(u16)]
+pub enum Flag {
+    /// Result is zero
+    Zero = 1 << 0,
+    /// Unsigned overflow or borrow, or the last bit shifted out
+    Carry = 1 << 1,
+    /// Top bit of the result is set
+    Sign = 1 << 2,
+    /// Signed overflow
+    Overflow = 1 << 3,
+}
+
+impl Machine {
+    /// Is `flag` set in the FLAGS register?
+    pub fn flag(&self, flag: Flag) -> bool {
+        self.registers[Register::FLAGS as usize] & flag as u16 != 0
+    }
+
+    /// Sets or clears a single flag
+    pub(crate) fn set_flag(&mut self, flag: Flag, value: bool) {
+        let flags = self.registers[Register::FLAGS as usize];
+        let flags = if value {
+            flags | flag as u16
+        } else {
+            flags & !(flag as u16)
+        };
+        self.write_register(Register::FLAGS, flags);
+    }
+
+    /// Sets every flag from the result of an ALU operation
+    pub(crate) fn set_flags(&mut self, result: u16, carry: bool, overflow: bool) {
+        let mut flags = 0;
+        if result == 0 {
+            flags |= Flag::Zero as u16;
+        }
+        if carry {
+            flags |= Flag::Carry as u16;
+        }
+        if result & 0x8000 != 0 {
+            flags |= Flag::Sign as u16;
+        }
+        if overflow {
+            flags |= Flag::Overflow as u16;
+        }
+        self.write_register(Register::FLAGS, flags);
+    }
+}
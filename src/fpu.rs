@@ -0,0 +1,236 @@
+use crate::fetch::Fetch;
+use crate::flags::Flag;
+use crate::history::Write;
+use crate::opcode::OpCode;
+use crate::{Machine, Register};
+
+/// Floating-point registers, each holds an `f64`
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FRegister {
+    F0,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    // Ignore, for other parts
+    FRegisterCount,
+}
+
+impl FRegister {
+    /// Floating-point registers, in encoding order
+    pub const ALL: [FRegister; 8] = [
+        FRegister::F0,
+        FRegister::F1,
+        FRegister::F2,
+        FRegister::F3,
+        FRegister::F4,
+        FRegister::F5,
+        FRegister::F6,
+        FRegister::F7,
+    ];
+}
+
+impl TryFrom<u8> for FRegister {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or(format!("{} is not a valid floating-point register", value))
+    }
+}
+
+impl TryFrom<&str> for FRegister {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        (0..Self::ALL.len())
+            .find(|n| value == format!("F{}", n))
+            .map(|n| Self::ALL[n])
+            .ok_or(format!("{} is not a valid floating-point register", value))
+    }
+}
+
+impl<'a> Fetch<'a> for FRegister {}
+
+/// Floating-point instructions
+///
+/// Operands
+/// - `FADD fd fa fb`, `FSUB`, `FMUL`, `FDIV`: `fd = fa op fb`
+/// - `FSQRT fd fs`, `FMOV fd fs`
+/// - `ITOF fd reg`: signed integer to float, `FTOI reg fs`: float to signed integer
+/// - `FCMP fa fb`
+/// - `FLD fd addr`: load the `f64` stored at `addr` in data
+///
+/// FLAGS
+/// - `FCMP`: Z when equal, C when `fa < fb`, O when either is NaN
+/// - `FTOI`: Z and S from the result, O when the float does not fit and was saturated
+impl Machine {
+    /// Turns the floating-point unit off, floating-point instructions then fail
+    pub fn disable_fpu(&mut self) {
+        self.fpu = false;
+    }
+
+    /// Gets the value of a floating-point register
+    pub fn fregister(&self, f: FRegister) -> f64 {
+        self.fregisters[f as usize]
+    }
+
+    /// Writes to a floating-point register, recording the old value if history is enabled
+    pub(crate) fn write_fregister(&mut self, f: FRegister, v: f64) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(Write::FRegister(f, self.fregisters[f as usize]));
+        }
+        self.fregisters[f as usize] = v;
+    }
+
+    /// Fetches a floating-point register and returns its value
+    fn fetch_fvalue(&mut self) -> Result<f64, String> {
+        let f: FRegister = self.fetch()?;
+        Ok(self.fregister(f))
+    }
+
+    /// Dispatches every floating-point opcode
+    pub(crate) fn handle_float(&mut self, op: OpCode) -> Result<(), String> {
+        if !self.fpu {
+            return Err(format!("{:?}: floating-point unit is disabled", op));
+        }
+
+        match op {
+            OpCode::FADD | OpCode::FSUB | OpCode::FMUL | OpCode::FDIV => {
+                let fd: FRegister = self.fetch()?;
+                let a = self.fetch_fvalue()?;
+                let b = self.fetch_fvalue()?;
+                let result = match op {
+                    OpCode::FADD => a + b,
+                    OpCode::FSUB => a - b,
+                    OpCode::FMUL => a * b,
+                    _ => a / b,
+                };
+                self.write_fregister(fd, result);
+                if self.debug {
+                    println!("| {:?}: Reg {:?} {}, {} -> {}", op, fd, a, b, result);
+                }
+            }
+            OpCode::FSQRT | OpCode::FMOV => {
+                let fd: FRegister = self.fetch()?;
+                let a = self.fetch_fvalue()?;
+                let result = if op == OpCode::FSQRT { a.sqrt() } else { a };
+                self.write_fregister(fd, result);
+                if self.debug {
+                    println!("| {:?}: Reg {:?} {} -> {}", op, fd, a, result);
+                }
+            }
+            OpCode::ITOF => {
+                let fd: FRegister = self.fetch()?;
+                let r: Register = self.fetch()?;
+                let result = self.register(r) as i16 as f64;
+                self.write_fregister(fd, result);
+                if self.debug {
+                    println!("| ITOF: Reg {:?} Reg {:?} -> {}", fd, r, result);
+                }
+            }
+            OpCode::FTOI => {
+                let r: Register = self.fetch()?;
+                let a = self.fetch_fvalue()?;
+                let fits = (i16::MIN as f64..=i16::MAX as f64).contains(&a.trunc());
+                // `as` saturates, and turns NaN into 0
+                let result = a as i16 as u16;
+                self.write_register(r, result);
+                self.set_flags(result, false, !fits);
+                if self.debug {
+                    println!("| FTOI: Reg {:?} {} -> 0x{:X}", r, a, result);
+                }
+            }
+            OpCode::FCMP => {
+                let a = self.fetch_fvalue()?;
+                let b = self.fetch_fvalue()?;
+                self.set_flags(1, a < b, a.is_nan() || b.is_nan());
+                self.set_flag(Flag::Zero, a == b);
+                if self.debug {
+                    println!("| FCMP: {} {}", a, b);
+                }
+            }
+            OpCode::FLD => {
+                let fd: FRegister = self.fetch()?;
+                let address: usize = self.fetch()?;
+                let bytes = self
+                    .data
+                    .get(address..address + 8)
+                    .ok_or(format!("FLD: data address 0x{:04X} out of bounds", address))?;
+                let result = f64::from_le_bytes(bytes.try_into().unwrap());
+                self.write_fregister(fd, result);
+                if self.debug {
+                    println!("| FLD: Reg {:?} [0x{:X}] -> {}", fd, address, result);
+                }
+            }
+            _ => unreachable!("{:?} is not a floating-point opcode", op),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{machine, run};
+
+    /// `FTOI A F0` with `F0` set, returns `A` and FLAGS as Z, S, O
+    fn ftoi(v: f64) -> (u16, [bool; 3]) {
+        let mut m = machine("FTOI A F0\nHALT");
+        m.fregisters[FRegister::F0 as usize] = v;
+        run(&mut m).unwrap();
+        let flags = [Flag::Zero, Flag::Sign, Flag::Overflow].map(|f| m.flag(f));
+        (m.register(Register::A), flags)
+    }
+
+    /// `FCMP F0 F1`, returns FLAGS as Z, C, O
+    fn fcmp(a: f64, b: f64) -> [bool; 3] {
+        let mut m = machine("FCMP F0 F1\nHALT");
+        m.fregisters[FRegister::F0 as usize] = a;
+        m.fregisters[FRegister::F1 as usize] = b;
+        run(&mut m).unwrap();
+        [Flag::Zero, Flag::Carry, Flag::Overflow].map(|f| m.flag(f))
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(ftoi(-2.7), (-2i16 as u16, [false, true, false]));
+        assert_eq!(ftoi(0.5), (0, [true, false, false]));
+        assert_eq!(ftoi(1e9), (i16::MAX as u16, [false, false, true]));
+        assert_eq!(ftoi(-1e9), (i16::MIN as u16, [false, true, true]));
+        assert_eq!(ftoi(f64::NAN), (0, [true, false, true]));
+
+        let mut m = machine("ITOF F0 A\nHALT");
+        m.registers[Register::A as usize] = -7i16 as u16;
+        run(&mut m).unwrap();
+        assert_eq!(m.fregister(FRegister::F0), -7.0);
+    }
+
+    #[test]
+    fn compares() {
+        assert_eq!(fcmp(1.0, 2.0), [false, true, false]);
+        assert_eq!(fcmp(2.0, 2.0), [true, false, false]);
+        assert_eq!(fcmp(3.0, 2.0), [false, false, false]);
+        assert_eq!(fcmp(f64::NAN, 2.0), [false, false, true]);
+    }
+
+    #[test]
+    fn float_literals() {
+        let mut m = machine(&std::fs::read_to_string("asm/float.asm").unwrap());
+        run(&mut m).unwrap();
+        assert_eq!(m.fregister(FRegister::F2), 3.0);
+        assert_eq!(m.register(Register::B), -2i16 as u16);
+        assert!(m.flag(Flag::Carry));
+
+        let mut m = machine("FADD F0 F0 F0\nHALT");
+        m.disable_fpu();
+        assert_eq!(
+            run(&mut m).unwrap_err(),
+            "FADD: floating-point unit is disabled"
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::flags::Flag;
+use crate::{Machine, Register};
+
+/// Returned in the destination register when a file-system syscall fails
+pub const ERROR: u16 = 0xFFFF;
+
+/// What a file descriptor refers to
+/// - File: An open file
+/// - Dir : Entries of a directory, and how many have been read
+#[derive(Debug)]
+enum Descriptor {
+    File(File),
+    Dir(Vec<String>, usize),
+}
+
+/// Per-machine file descriptor table, confined to a sandbox directory
+/// - Root : Every path is resolved inside this directory, without one every file-system syscall fails
+/// - Files: Open descriptors, 0, 1 and 2 are always stdin, stdout and stderr, stdin is read by the machine
+///
+/// Open files cannot be snapshotted and are not undone by reverse execution
+#[derive(Debug, Default)]
+pub struct FileSystem {
+    root: Option<PathBuf>,
+    files: Vec<Option<Descriptor>>,
+}
+
+impl FileSystem {
+    /// Descriptors below this are the standard streams
+    pub const FIRST_FD: usize = 3;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many descriptors the guest has open
+    pub fn open_files(&self) -> usize {
+        self.files.iter().flatten().count()
+    }
+
+    /// Confines the guest to `root`
+    pub fn set_root(&mut self, root: &Path) -> Result<(), String> {
+        let root = root
+            .canonicalize()
+            .map_err(|e| format!("could not open {}: {}", root.display(), e))?;
+        if !root.is_dir() {
+            return Err(format!("{} is not a directory", root.display()));
+        }
+        self.root = Some(root);
+        Ok(())
+    }
+
+    /// Resolves a guest path inside the root
+    /// Absolute paths and `..` are refused, and so are symlinks leading out of the root
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let root = self.root.as_ref().ok_or("no --fs-root given")?;
+        let relative = Path::new(path);
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!("{} is outside of the file-system root", path));
+        }
+        let full = root.join(relative);
+        // The file itself may not exist yet, its parent must and must stay inside the root
+        let parent = full
+            .parent()
+            .unwrap_or(root)
+            .canonicalize()
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let inside = match full.canonicalize() {
+            Ok(full) => full.starts_with(root),
+            // A dangling symlink could lead anywhere once written through
+            Err(_) if fs::symlink_metadata(&full).is_ok_and(|m| m.is_symlink()) => false,
+            Err(_) => parent.starts_with(root),
+        };
+        if inside {
+            Ok(full)
+        } else {
+            Err(format!("{} is outside of the file-system root", path))
+        }
+    }
+
+    fn insert(&mut self, descriptor: Descriptor) -> u16 {
+        let free = self.files.iter().position(Option::is_none);
+        let slot = free.unwrap_or_else(|| {
+            self.files.push(None);
+            self.files.len() - 1
+        });
+        self.files[slot] = Some(descriptor);
+        (slot + Self::FIRST_FD) as u16
+    }
+
+    fn get(&mut self, fd: u16) -> Result<&mut Descriptor, String> {
+        (fd as usize)
+            .checked_sub(Self::FIRST_FD)
+            .and_then(|slot| self.files.get_mut(slot))
+            .and_then(Option::as_mut)
+            .ok_or(format!("{} is not an open file descriptor", fd))
+    }
+
+    /// Opens `path`, mode 0 reads, 1 truncates, 2 appends and 3 reads and writes
+    /// Directories opened with mode 0 can be listed with [`FileSystem::read_dir`]
+    pub fn open(&mut self, path: &str, mode: u16) -> Result<u16, String> {
+        let full = self.resolve(path)?;
+        if mode == 0 && full.is_dir() {
+            let mut entries = fs::read_dir(&full)
+                .map_err(|e| format!("{}: {}", path, e))?
+                .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(|e| format!("{}: {}", path, e))?;
+            entries.sort();
+            return Ok(self.insert(Descriptor::Dir(entries, 0)));
+        }
+        let mut options = OpenOptions::new();
+        match mode {
+            0 => options.read(true),
+            1 => options.write(true).create(true).truncate(true),
+            2 => options.append(true).create(true),
+            3 => options.read(true).write(true).create(true),
+            _ => return Err(format!("unsupported open mode {}", mode)),
+        };
+        let file = options
+            .open(&full)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        Ok(self.insert(Descriptor::File(file)))
+    }
+
+    pub fn close(&mut self, fd: u16) -> Result<(), String> {
+        self.get(fd)?;
+        self.files[fd as usize - Self::FIRST_FD] = None;
+        while let Some(None) = self.files.last() {
+            self.files.pop();
+        }
+        Ok(())
+    }
+
+    pub fn read(&mut self, fd: u16, len: usize) -> Result<Vec<u8>, String> {
+        let mut buf = vec![0; len];
+        let n = match fd {
+            0..=2 => return Err(format!("{} is not readable", fd)),
+            _ => match self.get(fd)? {
+                Descriptor::File(file) => file.read(&mut buf),
+                Descriptor::Dir(..) => return Err(format!("{} is a directory", fd)),
+            },
+        }
+        .map_err(|e| e.to_string())?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    pub fn write(&mut self, fd: u16, bytes: &[u8]) -> Result<usize, String> {
+        match fd {
+            0 => Err("0 is not writable".to_string()),
+            1 => io::stdout().write(bytes).map_err(|e| e.to_string()),
+            2 => io::stderr().write(bytes).map_err(|e| e.to_string()),
+            _ => match self.get(fd)? {
+                Descriptor::File(file) => file.write(bytes).map_err(|e| e.to_string()),
+                Descriptor::Dir(..) => Err(format!("{} is a directory", fd)),
+            },
+        }
+    }
+
+    /// Moves the position of `fd`, `whence` is 0 for the start, 1 for the current position and 2 for the end
+    /// A position before the start or past `ERROR - 1` is an error and leaves the position unchanged
+    pub fn seek(&mut self, fd: u16, offset: i16, whence: u16) -> Result<u16, String> {
+        let from = match whence {
+            0 if offset < 0 => return Err(format!("negative position {}", offset)),
+            0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset as i64),
+            2 => SeekFrom::End(offset as i64),
+            _ => return Err(format!("unsupported seek origin {}", whence)),
+        };
+        let file = match self.get(fd)? {
+            Descriptor::File(file) => file,
+            Descriptor::Dir(..) => return Err(format!("{} is a directory", fd)),
+        };
+        let old = file.stream_position().map_err(|e| e.to_string())?;
+        let position = file.seek(from).map_err(|e| e.to_string())?;
+        if position >= ERROR as u64 {
+            file.seek(SeekFrom::Start(old)).map_err(|e| e.to_string())?;
+            return Err(format!("position {} does not fit in a register", position));
+        }
+        Ok(position as u16)
+    }
+
+    /// Kind (1 for files, 2 for directories) and size of `path`
+    pub fn stat(&self, path: &str) -> Result<(u16, u64), String> {
+        let metadata = fs::metadata(self.resolve(path)?).map_err(|e| format!("{}: {}", path, e))?;
+        let kind = if metadata.is_dir() { 2 } else { 1 };
+        Ok((kind, metadata.len()))
+    }
+
+    pub fn unlink(&self, path: &str) -> Result<(), String> {
+        fs::remove_file(self.resolve(path)?).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Next entry of a directory opened with `OPEN`, `None` once every entry was read
+    /// The entry stays the next one until `skip_dir` is called
+    pub fn read_dir(&mut self, fd: u16) -> Result<Option<String>, String> {
+        match self.get(fd)? {
+            Descriptor::Dir(entries, next) => Ok(entries.get(*next).cloned()),
+            Descriptor::File(_) => Err(format!("{} is not a directory", fd)),
+        }
+    }
+
+    /// Moves a directory past the entry last returned by `read_dir`
+    pub fn skip_dir(&mut self, fd: u16) -> Result<(), String> {
+        match self.get(fd)? {
+            Descriptor::Dir(entries, next) => {
+                *next = (*next + 1).min(entries.len());
+                Ok(())
+            }
+            Descriptor::File(_) => Err(format!("{} is not a directory", fd)),
+        }
+    }
+}
+
+/// File-system syscalls, every operand is a register
+/// Paths and buffers are in memory, paths are null terminated
+/// - `OPEN dst path mode`        : `dst` is the new descriptor, mode 0 reads, 1 truncates, 2 appends and 3 reads and writes
+/// - `CLOSE dst fd`
+/// - `READ dst fd buf len`       : `dst` is how many bytes were read, 0 and C set at the end of the file
+/// - `WRITE 0 dst fd buf len`    : `dst` is how many bytes were written, the mode 0 is an immediate
+/// - `SEEK dst fd offset whence` : `dst` is the new position, `offset` is signed, a position outside of 0..ERROR fails
+/// - `STAT dst path buf`         : writes the kind (1 file, 2 directory), then the size as a high and low word, all little endian
+/// - `UNLINK dst path`
+/// - `READDIR dst fd buf len`    : next entry of a directory opened with `OPEN`, null terminated, `dst` is its length, 0 once done, an entry that does not fit in `len` fails and stays the next one
+///
+/// `dst` is 0 on success unless said otherwise, and [`ERROR`] on failure
+impl Machine {
+    /// Confines file-system syscalls to `root`
+    pub fn set_fs_root(&mut self, root: &Path) -> Result<(), String> {
+        self.fs.set_root(root)
+    }
+
+    /// Reads a null terminated UTF-8 string from memory
+    pub(crate) fn read_string(&self, address: usize) -> Result<String, String> {
+        let bytes = self.read_cstr(address)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+    }
+
+    /// Fetches `N` registers and returns their values
+    pub(crate) fn fetch_values<const N: usize>(&mut self) -> Result<[u16; N], String> {
+        let mut values = [0; N];
+        for v in values.iter_mut() {
+            let r: Register = self.fetch()?;
+            *v = self.register(r);
+        }
+        Ok(values)
+    }
+
+    /// Puts the result of a file-system syscall in `dst`, failures become [`ERROR`]
+    fn fs_result(&mut self, name: &str, dst: Register, result: Result<u16, String>) {
+        let value = result.unwrap_or_else(|e| {
+            if self.debug {
+                println!("| {}: {}", name, e);
+            }
+            ERROR
+        });
+        self.write_register(dst, value);
+        if self.debug {
+            println!("| {}: Reg {:?} -> 0x{:04X}", name, dst, value);
+        }
+    }
+
+    pub(crate) fn syscall_open(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [path, mode] = self.fetch_values()?;
+        let result = self
+            .read_string(path as usize)
+            .and_then(|path| self.fs.open(&path, mode));
+        self.fs_result("OPEN", dst, result);
+        Ok(())
+    }
+
+    pub(crate) fn syscall_close(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [fd] = self.fetch_values()?;
+        let result = self.fs.close(fd).map(|_| 0);
+        self.fs_result("CLOSE", dst, result);
+        Ok(())
+    }
+
+    pub(crate) fn syscall_read(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [fd, buf, len] = self.fetch_values()?;
+        let result = self
+            .memory_range(buf as usize, len as usize)
+            .and_then(|_| match fd {
+                0 => self.read_stdin(len as usize),
+                _ => self.fs.read(fd, len as usize),
+            })
+            .and_then(|bytes| {
+                self.write_memory(buf as usize, &bytes)?;
+                Ok(bytes.len() as u16)
+            });
+        let eof = result == Ok(0) && len > 0;
+        self.fs_result("READ", dst, result);
+        self.set_flag(Flag::Carry, eof);
+        Ok(())
+    }
+
+    /// Descriptor form of `WRITE`, after its mode
+    pub(crate) fn syscall_write_fd(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [fd, buf, len] = self.fetch_values()?;
+        let result = self
+            .read_memory(buf as usize, len as usize)
+            .map(<[u8]>::to_vec)
+            .and_then(|bytes| self.fs.write(fd, &bytes))
+            .map(|n| n as u16);
+        self.fs_result("WRITE", dst, result);
+        Ok(())
+    }
+
+    pub(crate) fn syscall_seek(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [fd, offset, whence] = self.fetch_values()?;
+        let result = self.fs.seek(fd, offset as i16, whence);
+        self.fs_result("SEEK", dst, result);
+        Ok(())
+    }
+
+    pub(crate) fn syscall_stat(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [path, buf] = self.fetch_values()?;
+        let result = self
+            .read_string(path as usize)
+            .and_then(|path| self.fs.stat(&path))
+            .and_then(|(kind, size)| {
+                let size = size.min(u32::MAX as u64) as u32;
+                let mut bytes = kind.to_le_bytes().to_vec();
+                bytes.extend_from_slice(&((size >> 16) as u16).to_le_bytes());
+                bytes.extend_from_slice(&(size as u16).to_le_bytes());
+                self.write_memory(buf as usize, &bytes)?;
+                Ok(0)
+            });
+        self.fs_result("STAT", dst, result);
+        Ok(())
+    }
+
+    pub(crate) fn syscall_unlink(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [path] = self.fetch_values()?;
+        let result = self
+            .read_string(path as usize)
+            .and_then(|path| self.fs.unlink(&path))
+            .map(|_| 0);
+        self.fs_result("UNLINK", dst, result);
+        Ok(())
+    }
+
+    pub(crate) fn syscall_readdir(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let [fd, buf, len] = self.fetch_values()?;
+        let result = self.fs.read_dir(fd).and_then(|entry| {
+            let Some(name) = entry else {
+                return Ok(0);
+            };
+            let mut bytes = name.into_bytes();
+            if bytes.len() + 1 > len as usize {
+                return Err(format!("entry does not fit in {} bytes", len));
+            }
+            let n = bytes.len() as u16;
+            bytes.push(0);
+            self.write_memory(buf as usize, &bytes)?;
+            // Only consumed once written, a short buffer can be retried with a bigger one
+            self.fs.skip_dir(fd)?;
+            Ok(n)
+        });
+        self.fs_result("READDIR", dst, result);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcode::OpCode;
+    use crate::syscall::Syscall;
+
+    /// Fresh directory under the temporary directory, `name` must be unique to the test
+    fn root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("novavm-fs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_root() {
+        let root = root("symlinks");
+        let outside = root.with_extension("outside");
+        std::os::unix::fs::symlink(&outside, root.join("dangling")).unwrap();
+        std::os::unix::fs::symlink(root.join("file"), root.join("inside")).unwrap();
+        let mut fs = FileSystem::new();
+        fs.set_root(&root).unwrap();
+
+        assert_eq!(
+            fs.open("dangling", 1).unwrap_err(),
+            "dangling is outside of the file-system root"
+        );
+        assert!(!outside.exists());
+        // Symlinks staying inside work once their target exists
+        assert!(fs.open("inside", 1).is_err());
+        File::create(root.join("file")).unwrap();
+        assert!(fs.open("inside", 1).is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn readdir_short_buffer() {
+        let root = root("readdir");
+        File::create(root.join("entry")).unwrap();
+        let mut m = Machine::new();
+        m.set_fs_root(&root).unwrap();
+        let fd = m.fs.open(".", 0).unwrap();
+        // SYSCALL READDIR R0 R1 R2 R3, twice
+        let readdir = [OpCode::SYSCALL as u8, Syscall::READDIR as u8, 0, 1, 2, 3];
+        m.set_memory(&[readdir, readdir].concat());
+        m.registers[Register::R1 as usize] = fd;
+        m.registers[Register::R2 as usize] = 0x100;
+        m.registers[Register::R3 as usize] = 4;
+
+        m.step().unwrap();
+        assert_eq!(m.register(Register::R0), ERROR);
+        m.registers[Register::R3 as usize] = 6;
+        m.step().unwrap();
+        assert_eq!(m.register(Register::R0), 5);
+        assert_eq!(&m.memory[0x100..0x106], b"entry\0");
+        assert_eq!(m.fs.read_dir(fd).unwrap(), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn seek_out_of_range() {
+        let root = root("seek");
+        fs::write(root.join("file"), b"abc").unwrap();
+        let mut fs = FileSystem::new();
+        fs.set_root(&root).unwrap();
+        let fd = fs.open("file", 3).unwrap();
+
+        assert_eq!(fs.seek(fd, 2, 0), Ok(2));
+        assert_eq!(fs.seek(fd, -1, 0).unwrap_err(), "negative position -1");
+        assert!(fs.seek(fd, -3, 1).is_err());
+        assert_eq!(fs.seek(fd, 0, 1), Ok(2));
+        fs.seek(fd, i16::MAX, 0).unwrap();
+        assert_eq!(fs.seek(fd, i16::MAX, 1), Ok(ERROR - 1));
+        assert_eq!(
+            fs.seek(fd, 1, 1).unwrap_err(),
+            "position 65535 does not fit in a register"
+        );
+        assert_eq!(fs.seek(fd, 0, 1), Ok(ERROR - 1));
+        assert_eq!(fs.seek(fd, -1, 2), Ok(2));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,418 @@
+use crate::history::Write;
+use crate::opcode::OpCode;
+use crate::{Machine, Register};
+
+/// A garbage-collected value
+/// - Str   : Bytes of a string
+/// - Array : Words, any of which may be a handle to another object
+/// - Record: Fixed amount of fields, any of which may be a handle to another object
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Str(Vec<u8>),
+    Array(Vec<u16>),
+    Record(Vec<u16>),
+}
+
+impl Object {
+    /// Type tag, as returned by `OTYPE`
+    pub fn tag(&self) -> u8 {
+        match self {
+            Object::Str(_) => 1,
+            Object::Array(_) => 2,
+            Object::Record(_) => 3,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Object::Str(bytes) => bytes.len(),
+            Object::Array(words) | Object::Record(words) => words.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Words that may refer to other objects
+    fn children(&self) -> &[u16] {
+        match self {
+            Object::Str(_) => &[],
+            Object::Array(words) | Object::Record(words) => words,
+        }
+    }
+}
+
+/// Managed object heap, objects live on the host and are referenced by handles
+/// - Objects: Slot per handle, `None` when free
+/// - Allocated: Objects allocated since the last collection
+///
+/// Handles have the top two bits set so they stand apart from small integers,
+/// roots are found conservatively: any register that is a live handle keeps it alive
+#[derive(Debug, Default, Clone)]
+pub struct ObjectHeap {
+    objects: Vec<Option<Object>>,
+    pub(crate) allocated: usize,
+}
+
+impl ObjectHeap {
+    /// Bits set in every handle
+    pub const HANDLE_TAG: u16 = 0xC000;
+    /// Most objects alive at once
+    pub const CAPACITY: usize = 0x4000;
+    /// Allocations between automatic collections
+    pub const THRESHOLD: usize = 256;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Slot of a handle, whether or not it refers to a live object
+    fn handle_slot(handle: u16) -> usize {
+        (handle & !Self::HANDLE_TAG) as usize
+    }
+
+    /// Slot of a handle, if it refers to a live object
+    fn slot(&self, handle: u16) -> Option<usize> {
+        if handle & Self::HANDLE_TAG != Self::HANDLE_TAG {
+            return None;
+        }
+        let slot = Self::handle_slot(handle);
+        matches!(self.objects.get(slot), Some(Some(_))).then_some(slot)
+    }
+
+    /// Should the next allocation collect first?
+    pub fn wants_collection(&self) -> bool {
+        self.allocated >= Self::THRESHOLD || self.live() == Self::CAPACITY
+    }
+
+    /// Stores an object, returning its handle or `None` when the heap is full
+    pub fn alloc(&mut self, object: Object) -> Option<u16> {
+        let slot = match self.objects.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None if self.objects.len() < Self::CAPACITY => {
+                self.objects.push(None);
+                self.objects.len() - 1
+            }
+            None => return None,
+        };
+        self.objects[slot] = Some(object);
+        self.allocated += 1;
+        Some(slot as u16 | Self::HANDLE_TAG)
+    }
+
+    pub fn get(&self, handle: u16) -> Option<&Object> {
+        self.objects[self.slot(handle)?].as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: u16) -> Option<&mut Object> {
+        let slot = self.slot(handle)?;
+        self.objects[slot].as_mut()
+    }
+
+    /// How many objects are alive
+    pub fn live(&self) -> usize {
+        self.objects.iter().flatten().count()
+    }
+
+    /// Puts `object` in `slot`, returning what was there, for undoing
+    pub(crate) fn replace(&mut self, slot: usize, object: Option<Object>) -> Option<Object> {
+        if slot >= self.objects.len() {
+            self.objects.resize(slot + 1, None);
+        }
+        let old = std::mem::replace(&mut self.objects[slot], object);
+        while let Some(None) = self.objects.last() {
+            self.objects.pop();
+        }
+        old
+    }
+
+    /// Mark and sweep, everything not reachable from `roots` is freed
+    /// Returns the freed objects and their slots
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = u16>) -> Vec<(usize, Object)> {
+        let mut marked = vec![false; self.objects.len()];
+        let mut pending: Vec<u16> = roots.into_iter().collect();
+        while let Some(handle) = pending.pop() {
+            let Some(slot) = self.slot(handle) else {
+                continue;
+            };
+            if marked[slot] {
+                continue;
+            }
+            marked[slot] = true;
+            if let Some(object) = &self.objects[slot] {
+                pending.extend_from_slice(object.children());
+            }
+        }
+
+        let mut freed = Vec::new();
+        for (slot, (object, marked)) in self.objects.iter_mut().zip(marked).enumerate() {
+            if !marked {
+                if let Some(object) = object.take() {
+                    freed.push((slot, object));
+                }
+            }
+        }
+        while let Some(None) = self.objects.last() {
+            self.objects.pop();
+        }
+        self.allocated = 0;
+        freed
+    }
+
+    /// Serialises the object heap for snapshots
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(self.allocated as u16).to_le_bytes());
+        bytes.extend_from_slice(&(self.objects.len() as u16).to_le_bytes());
+        for object in &self.objects {
+            let Some(object) = object else {
+                bytes.push(0);
+                continue;
+            };
+            bytes.push(object.tag());
+            bytes.extend_from_slice(&(object.len() as u16).to_le_bytes());
+            match object {
+                Object::Str(s) => bytes.extend_from_slice(s),
+                Object::Array(words) | Object::Record(words) => {
+                    for w in words {
+                        bytes.extend_from_slice(&w.to_le_bytes());
+                    }
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Reads an object heap written by [`ObjectHeap::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let invalid = || "invalid objects section".to_string();
+        let mut rest = bytes;
+        let mut take = |n: usize| -> Result<&[u8], String> {
+            let (head, tail) = rest.split_at_checked(n).ok_or_else(invalid)?;
+            rest = tail;
+            Ok(head)
+        };
+        let word = |b: &[u8]| u16::from_le_bytes([b[0], b[1]]);
+
+        let allocated = word(take(2)?) as usize;
+        let count = word(take(2)?) as usize;
+        let mut objects = Vec::with_capacity(count);
+        for _ in 0..count {
+            let tag = take(1)?[0];
+            if tag == 0 {
+                objects.push(None);
+                continue;
+            }
+            let len = word(take(2)?) as usize;
+            let object = match tag {
+                1 => Object::Str(take(len)?.to_vec()),
+                2 | 3 => {
+                    let words = take(len * 2)?.chunks_exact(2).map(word).collect();
+                    if tag == 2 {
+                        Object::Array(words)
+                    } else {
+                        Object::Record(words)
+                    }
+                }
+                _ => return Err(invalid()),
+            };
+            objects.push(Some(object));
+        }
+        Ok(Self { objects, allocated })
+    }
+}
+
+/// Object instructions
+///
+/// Operands
+/// - `NEWSTR dst addr`   : string from the null terminated text at `addr` in data
+/// - `NEWARR dst len`    : array of `len` zeroes, `len` is a register
+/// - `NEWREC dst n`      : record of `n` zeroed fields, `n` is an immediate
+/// - `GETF dst obj i`    : `dst = obj[i]`, `i` is a register, strings give their bytes
+/// - `SETF obj i src`    : `obj[i] = src`, `i` is a register, strings store the low byte
+/// - `OLEN dst obj`      : length of `obj`
+/// - `OTYPE dst obj`     : 1 for strings, 2 for arrays, 3 for records, 0 for anything else
+/// - `GC`                : collect now, `A` is how many objects were freed
+///
+/// Roots are every register
+impl Machine {
+    /// Managed objects, for inspection
+    pub fn objects(&self) -> &ObjectHeap {
+        &self.objects
+    }
+
+    /// Records the write built by `write`, which is only called when history is enabled
+    fn record_objects(&mut self, write: impl FnOnce(&ObjectHeap) -> Write) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(write(&self.objects));
+        }
+    }
+
+    /// Collects, returning how many objects were freed
+    pub fn collect_garbage(&mut self) -> usize {
+        self.record_objects(|objects| Write::Allocated(objects.allocated));
+        let freed = self.objects.collect(self.registers);
+        let count = freed.len();
+        for (slot, object) in freed {
+            let old = Some(object);
+            self.record_objects(|_| Write::Object { slot, old });
+        }
+        if self.debug {
+            println!("| GC: {} freed, {} live", count, self.objects.live());
+        }
+        count
+    }
+
+    /// Allocates an object, collecting first when the heap asks for it
+    fn alloc_object(&mut self, op: OpCode, object: Object) -> Result<u16, String> {
+        if self.objects.wants_collection() {
+            self.collect_garbage();
+        }
+        self.record_objects(|objects| Write::Allocated(objects.allocated));
+        let handle = self
+            .objects
+            .alloc(object)
+            .ok_or(format!("{:?}: object heap is full", op))?;
+        let slot = ObjectHeap::handle_slot(handle);
+        self.record_objects(|_| Write::Object { slot, old: None });
+        Ok(handle)
+    }
+
+    fn object(&self, op: OpCode, handle: u16) -> Result<&Object, String> {
+        self.objects
+            .get(handle)
+            .ok_or(format!("{:?}: 0x{:04X} is not an object", op, handle))
+    }
+
+    /// Dispatches every object opcode
+    pub(crate) fn handle_object(&mut self, op: OpCode) -> Result<(), String> {
+        match op {
+            OpCode::NEWSTR | OpCode::NEWARR | OpCode::NEWREC => {
+                let dst: Register = self.fetch()?;
+                let object = match op {
+                    OpCode::NEWSTR => {
+                        let address: usize = self.fetch()?;
+                        let text = self.data.get(address..).ok_or(format!(
+                            "NEWSTR: data address 0x{:04X} out of bounds",
+                            address
+                        ))?;
+                        let len = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+                        Object::Str(text[..len].to_vec())
+                    }
+                    OpCode::NEWARR => {
+                        let len: Register = self.fetch()?;
+                        Object::Array(vec![0; self.register(len) as usize])
+                    }
+                    _ => {
+                        let n: u8 = self.fetch()?;
+                        Object::Record(vec![0; n as usize])
+                    }
+                };
+                let len = object.len();
+                let handle = self.alloc_object(op, object)?;
+                self.write_register(dst, handle);
+                if self.debug {
+                    println!("| {:?}: Reg {:?} {} -> 0x{:04X}", op, dst, len, handle);
+                }
+            }
+            OpCode::GETF => {
+                let dst: Register = self.fetch()?;
+                let obj: Register = self.fetch()?;
+                let i: Register = self.fetch()?;
+                let (handle, i) = (self.register(obj), self.register(i) as usize);
+                let value = match self.object(op, handle)? {
+                    Object::Str(bytes) => bytes.get(i).map(|&b| b as u16),
+                    Object::Array(words) | Object::Record(words) => words.get(i).copied(),
+                }
+                .ok_or(format!("GETF: index {} out of bounds", i))?;
+                self.write_register(dst, value);
+                if self.debug {
+                    println!(
+                        "| GETF: Reg {:?} 0x{:04X}[{}] -> 0x{:X}",
+                        dst, handle, i, value
+                    );
+                }
+            }
+            OpCode::SETF => {
+                let obj: Register = self.fetch()?;
+                let i: Register = self.fetch()?;
+                let src: Register = self.fetch()?;
+                let (handle, i) = (self.register(obj), self.register(i) as usize);
+                let value = self.register(src);
+                let len = self.object(op, handle)?.len();
+                if i >= len {
+                    return Err(format!("SETF: index {} out of bounds", i));
+                }
+                self.record_objects(|objects| Write::Object {
+                    slot: ObjectHeap::handle_slot(handle),
+                    old: objects.get(handle).cloned(),
+                });
+                match self.objects.get_mut(handle) {
+                    Some(Object::Str(bytes)) => bytes[i] = value as u8,
+                    Some(Object::Array(words) | Object::Record(words)) => words[i] = value,
+                    None => {}
+                }
+                if self.debug {
+                    println!("| SETF: 0x{:04X}[{}] = 0x{:X}", handle, i, value);
+                }
+            }
+            OpCode::OLEN | OpCode::OTYPE => {
+                let dst: Register = self.fetch()?;
+                let obj: Register = self.fetch()?;
+                let handle = self.register(obj);
+                let value = if op == OpCode::OLEN {
+                    self.object(op, handle)?.len() as u16
+                } else {
+                    self.objects.get(handle).map_or(0, |o| o.tag() as u16)
+                };
+                self.write_register(dst, value);
+                if self.debug {
+                    println!("| {:?}: Reg {:?} 0x{:04X} -> {}", op, dst, handle, value);
+                }
+            }
+            OpCode::GC => {
+                let freed = self.collect_garbage();
+                self.write_register(Register::A, freed as u16);
+            }
+            _ => unreachable!("{:?} is not an object opcode", op),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{machine, run};
+
+    #[test]
+    fn registers_are_the_roots() {
+        let mut m = machine("NEWREC R4 $1\nNEWARR R5 R0\nSETF R4 R0 R5\nADD R5 $0 $0\nNEWSTR R6 $0\nADD R6 $0 $0\nGC\nHALT\n[[DATA]]Hi");
+        run(&mut m).unwrap();
+        // The array lives through the record, the string only was in a register
+        assert_eq!(m.register(Register::A), 1);
+        assert_eq!(m.objects.live(), 2);
+    }
+
+    #[test]
+    fn undo() {
+        let mut m = machine("NEWSTR R4 $0\nNEWSTR R5 $0\nADD R5 $0 $0\nGC\nHALT\n[[DATA]]Hi");
+        m.enable_history();
+        run(&mut m).unwrap();
+        assert_eq!(m.objects.live(), 1);
+        assert_eq!(m.objects.allocated, 0);
+
+        // HALT, then GC brings the string back and the count of allocations since the last one
+        m.step_back().unwrap();
+        m.step_back().unwrap();
+        assert_eq!(m.objects.live(), 2);
+        assert_eq!(m.objects.allocated, 2);
+        m.step_back().unwrap();
+        assert!(m.objects.get(m.register(Register::R5)).is_some());
+        m.step_back().unwrap();
+        m.step_back().unwrap();
+        assert_eq!(m.objects.live(), 0);
+        assert_eq!(m.objects.allocated, 0);
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+
+use crate::history::Write;
+use crate::{Machine, Register};
+
+/// A change to a single block, holding what it overwrote
+/// - Freed  : Whether the block is a freed one rather than a live one
+/// - Address: Start of the block
+/// - Old    : Its size before the change, `None` when there was no such block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockChange {
+    pub freed: bool,
+    pub address: usize,
+    pub old: Option<usize>,
+}
+
+/// Host-side allocator for the guest heap, a fixed region of memory
+/// - Allocations: Live blocks, address to size
+/// - Freed      : Freed blocks that have not been handed out again, used to report use after free
+/// - Checked    : Report double frees and accesses to freed or unallocated heap memory
+/// - End        : One past the last address of the heap, lowered to make room for arguments
+/// - Changes    : Block changes not taken yet, for the undo log
+///
+/// Address 0 is never part of the heap, so it is used as the null pointer
+#[derive(Debug, Clone)]
+pub struct Heap {
+    allocations: BTreeMap<usize, usize>,
+    freed: BTreeMap<usize, usize>,
+    pub checked: bool,
+    end: usize,
+    changes: Vec<BlockChange>,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            allocations: BTreeMap::new(),
+            freed: BTreeMap::new(),
+            checked: false,
+            end: Self::END,
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl Heap {
+    /// First address of the heap
+    pub const START: usize = 0x800;
+    /// One past the last address of the heap, unless lowered
+    pub const END: usize = Machine::MEMORY_LENGTH;
+    /// Every block starts on a multiple of this
+    pub const ALIGN: usize = 2;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// One past the last address of the heap
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Lowers the end of the heap, the memory above it is left alone
+    pub fn set_end(&mut self, end: usize) {
+        self.end = end.clamp(Self::START, Self::END);
+    }
+
+    /// Sets the size of a block, `None` removes it, and returns the old one
+    fn set_block(&mut self, freed: bool, address: usize, size: Option<usize>) -> Option<usize> {
+        let blocks = if freed {
+            &mut self.freed
+        } else {
+            &mut self.allocations
+        };
+        match size {
+            Some(size) => blocks.insert(address, size),
+            None => blocks.remove(&address),
+        }
+    }
+
+    /// Like `set_block`, remembering the change
+    fn change_block(&mut self, freed: bool, address: usize, size: Option<usize>) -> Option<usize> {
+        let old = self.set_block(freed, address, size);
+        self.changes.push(BlockChange {
+            freed,
+            address,
+            old,
+        });
+        old
+    }
+
+    /// Block changes made since the last call, oldest first
+    pub(crate) fn take_changes(&mut self) -> Vec<BlockChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Reverts a block change
+    pub(crate) fn undo(&mut self, change: BlockChange) {
+        self.set_block(change.freed, change.address, change.old);
+    }
+
+    /// Reverts every change not taken yet
+    fn rollback(&mut self) {
+        for change in self.take_changes().into_iter().rev() {
+            self.undo(change);
+        }
+    }
+
+    /// Finds room for `size` bytes, first fit
+    /// Returns `None` when the heap is full
+    pub fn alloc(&mut self, size: usize) -> Option<usize> {
+        let size = size.max(1).div_ceil(Self::ALIGN) * Self::ALIGN;
+        let mut start = Self::START;
+        for (&address, &len) in &self.allocations {
+            if address - start >= size {
+                break;
+            }
+            start = (address + len).div_ceil(Self::ALIGN) * Self::ALIGN;
+        }
+        if start + size > self.end {
+            return None;
+        }
+        self.change_block(false, start, Some(size));
+        let reused: Vec<usize> = self
+            .freed
+            .iter()
+            .filter(|(&a, &len)| a + len > start && a < start + size)
+            .map(|(&a, _)| a)
+            .collect();
+        for address in reused {
+            self.change_block(true, address, None);
+        }
+        Some(start)
+    }
+
+    /// Frees the block at `address`
+    /// Fails for addresses that are not the start of a live block
+    pub fn free(&mut self, address: usize) -> Result<usize, String> {
+        match self.allocations.get(&address).copied() {
+            Some(size) => {
+                self.change_block(false, address, None);
+                self.change_block(true, address, Some(size));
+                Ok(size)
+            }
+            None if self.freed.contains_key(&address) => {
+                Err(format!("double free of 0x{:04X}", address))
+            }
+            None => Err(format!("free of unallocated address 0x{:04X}", address)),
+        }
+    }
+
+    /// Size of the live block starting at `address`
+    pub fn size(&self, address: usize) -> Option<usize> {
+        self.allocations.get(&address).copied()
+    }
+
+    /// Checks an access to `len` bytes at `address`
+    /// Accesses outside the heap are always fine, inside the heap they must stay in a live block
+    pub fn check_access(&self, address: usize, len: usize) -> Result<(), String> {
+        let end = address + len;
+        if len == 0 || end <= Self::START || address >= self.end {
+            return Ok(());
+        }
+        let live = self
+            .allocations
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(&a, &size)| end <= a + size);
+        if live {
+            return Ok(());
+        }
+        let freed = self
+            .freed
+            .iter()
+            .any(|(&a, &size)| address < a + size && a < end);
+        if freed {
+            Err(format!("use after free at 0x{:04X}+{}", address, len))
+        } else {
+            Err(format!(
+                "access to unallocated heap memory at 0x{:04X}+{}",
+                address, len
+            ))
+        }
+    }
+
+    /// Live blocks, address to size
+    pub fn leaks(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.allocations.iter().map(|(&a, &s)| (a, s))
+    }
+
+    /// Serialises the heap for snapshots
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.checked as u8];
+        for blocks in [&self.allocations, &self.freed] {
+            bytes.extend_from_slice(&(blocks.len() as u16).to_le_bytes());
+            for (&a, &s) in blocks {
+                bytes.extend_from_slice(&(a as u16).to_le_bytes());
+                bytes.extend_from_slice(&(s as u16).to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&(self.end as u16).to_le_bytes());
+        bytes
+    }
+
+    /// Reads a heap written by [`Heap::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let invalid = || "invalid heap section".to_string();
+        let mut heap = Self::new();
+        heap.checked = *bytes.first().ok_or_else(invalid)? != 0;
+        let mut rest = &bytes[1..];
+        for blocks in [&mut heap.allocations, &mut heap.freed] {
+            let count = rest.get(..2).ok_or_else(invalid)?;
+            let count = u16::from_le_bytes([count[0], count[1]]) as usize;
+            let entries = rest.get(2..2 + count * 4).ok_or_else(invalid)?;
+            for e in entries.chunks_exact(4) {
+                let a = u16::from_le_bytes([e[0], e[1]]) as usize;
+                let s = u16::from_le_bytes([e[2], e[3]]) as usize;
+                blocks.insert(a, s);
+            }
+            rest = &rest[2 + count * 4..];
+        }
+        // Older snapshots end here, with the heap reaching the end of memory
+        if let Some(end) = rest.get(..2) {
+            heap.set_end(u16::from_le_bytes([end[0], end[1]]) as usize);
+        }
+        Ok(heap)
+    }
+}
+
+/// Heap syscalls, every operand is a register
+/// - `ALLOC dst size`        : `dst` is the new block, 0 when the heap is full
+/// - `REALLOC dst ptr size`  : resize `ptr`, keeping its contents, `dst` is the new block
+/// - `FREE ptr`              : free `ptr`, freeing 0 does nothing
+///
+/// A `ptr` that is not the start of a live block is an error in checked mode, otherwise it is
+/// left alone: `FREE` does nothing and `REALLOC` puts 0 in `dst`
+impl Machine {
+    /// Reports double frees, use after free and accesses to unallocated heap memory
+    pub fn enable_heap_check(&mut self) {
+        self.heap.checked = true;
+    }
+
+    /// Heap state, for leak reports
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Moves the heap's block changes into the undo log if history is enabled
+    fn record_heap(&mut self) {
+        let changes = self.heap.take_changes();
+        if let Some(history) = self.history.as_mut() {
+            for change in changes {
+                history.record(Write::Block(change));
+            }
+        }
+    }
+
+    pub(crate) fn syscall_alloc(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let size: Register = self.fetch()?;
+        let size = self.register(size) as usize;
+
+        let address = self.heap.alloc(size).unwrap_or(0);
+        self.record_heap();
+        self.write_register(dst, address as u16);
+        if self.debug {
+            println!("| ALLOC: {} bytes -> 0x{:04X}", size, address);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn syscall_realloc(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let ptr: Register = self.fetch()?;
+        let size: Register = self.fetch()?;
+        let ptr = self.register(ptr) as usize;
+        let size = self.register(size) as usize;
+
+        let old_size = if ptr == 0 {
+            0
+        } else {
+            match self.heap.free(ptr) {
+                Ok(s) => s,
+                Err(e) if self.heap.checked => return Err(format!("REALLOC: {}", e)),
+                Err(_) => {
+                    self.write_register(dst, 0);
+                    return Ok(());
+                }
+            }
+        };
+        let address = if size == 0 {
+            0
+        } else {
+            self.heap.alloc(size).unwrap_or(0)
+        };
+
+        if address == 0 && size != 0 {
+            // Out of memory, the old block stays valid
+            self.heap.rollback();
+            self.write_register(dst, 0);
+            return Ok(());
+        }
+        let contents = self.memory[ptr..ptr + old_size.min(size)].to_vec();
+        self.record_heap();
+        self.write_memory(address, &contents)?;
+        self.write_register(dst, address as u16);
+        if self.debug {
+            println!(
+                "| REALLOC: 0x{:04X} to {} bytes -> 0x{:04X}",
+                ptr, size, address
+            );
+        }
+        Ok(())
+    }
+
+    pub(crate) fn syscall_free(&mut self) -> Result<(), String> {
+        let ptr: Register = self.fetch()?;
+        let ptr = self.register(ptr) as usize;
+        if ptr == 0 {
+            return Ok(());
+        }
+
+        match self.heap.free(ptr) {
+            Ok(_) => self.record_heap(),
+            Err(e) if self.heap.checked => return Err(format!("FREE: {}", e)),
+            Err(_) => {}
+        }
+        if self.debug {
+            println!("| FREE: 0x{:04X}", ptr);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{machine, run};
+
+    #[test]
+    fn undo() {
+        let mut m = machine(
+            "ADD C $16 $0\nSYSCALL ALLOC A C\nADD C $32 $0\nSYSCALL REALLOC B A C\n\
+             SYSCALL FREE B\nHALT",
+        );
+        m.enable_history();
+        run(&mut m).unwrap();
+        let (a, b) = (
+            m.register(Register::A) as usize,
+            m.register(Register::B) as usize,
+        );
+        assert_eq!(m.heap.leaks().count(), 0);
+
+        // HALT, then FREE brings the grown block back
+        m.step_back().unwrap();
+        m.step_back().unwrap();
+        assert_eq!(m.heap.leaks().collect::<Vec<_>>(), [(b, 32)]);
+        // REALLOC gives back the original block, and its size
+        m.step_back().unwrap();
+        assert_eq!(m.heap.leaks().collect::<Vec<_>>(), [(a, 16)]);
+        m.step_back().unwrap();
+        m.step_back().unwrap();
+        assert_eq!(m.heap.leaks().count(), 0);
+        assert_eq!(m.heap.to_bytes(), Heap::new().to_bytes());
+    }
+
+    #[test]
+    fn invalid_pointers() {
+        // 0x900 is in the heap but was never allocated
+        let setup = "ADD R4 $0x90 $0\nADD R5 $16 $0\nMULR R4 R4 R5\nADD B $1 $0\n";
+        for syscall in ["FREE R4", "REALLOC B R4 B"] {
+            let source = format!("{}SYSCALL {}\nHALT", setup, syscall);
+            let mut m = machine(&source);
+            run(&mut m).unwrap();
+            assert_eq!(m.register(Register::B), syscall.starts_with("FREE") as u16);
+            assert_eq!(m.heap.leaks().count(), 0);
+
+            let mut m = machine(&source);
+            m.enable_heap_check();
+            let name = syscall.split(' ').next().unwrap();
+            assert_eq!(
+                run(&mut m).unwrap_err(),
+                format!("{}: free of unallocated address 0x0900", name)
+            );
+        }
+    }
+
+    #[test]
+    fn realloc_out_of_memory() {
+        // The heap is 0x800 bytes, growing the block past it fails
+        let mut m = machine(
+            "ADD R4 $16 $0\nADD C $0x70 $0\nMULR C C R4\nSYSCALL ALLOC A C\n\
+             ADD B $0x81 $0\nMULR B B R4\nSYSCALL REALLOC B A B\nHALT",
+        );
+        run(&mut m).unwrap();
+        assert_eq!(m.register(Register::B), 0);
+        assert_eq!(m.heap.leaks().collect::<Vec<_>>(), [(Heap::START, 0x700)]);
+        assert!(m.heap.take_changes().is_empty());
+    }
+}
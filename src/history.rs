@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+
+use crate::fpu::FRegister;
+use crate::gc::Object;
+use crate::heap::BlockChange;
+use crate::random::Rng;
+use crate::{Machine, Register};
+
+/// A single write made by an instruction, holding the value it overwrote
+#[derive(Debug, Clone)]
+pub enum Write {
+    Register(Register, u16),
+    FRegister(FRegister, f64),
+    Memory(usize, u8),
+    Block(BlockChange),
+    Object { slot: usize, old: Option<Object> },
+    Allocated(usize),
+    Random(Rng),
+}
+
+/// Everything needed to undo one executed instruction
+/// - Count : Instruction count before the instruction ran
+/// - PC    : Program counter before the instruction ran
+/// - Halt  : Halt flag before the instruction ran
+/// - Writes: Overwritten values, in the order they were written
+#[derive(Debug)]
+pub struct Step {
+    pub count: u64,
+    pub pc: u16,
+    pub halt: bool,
+    pub writes: Vec<Write>,
+}
+
+/// Per-step undo log used for reverse execution
+/// Only the last `limit` steps are kept
+#[derive(Debug)]
+pub struct History {
+    steps: VecDeque<Step>,
+    limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    /// Default amount of steps kept in the undo log
+    pub const DEFAULT_LIMIT: usize = 1_000_000;
+
+    pub fn new() -> Self {
+        Self::with_limit(Self::DEFAULT_LIMIT)
+    }
+
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            steps: VecDeque::new(),
+            limit,
+        }
+    }
+
+    /// Starts recording a new step
+    pub fn begin(&mut self, count: u64, pc: u16, halt: bool) {
+        if self.steps.len() == self.limit {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(Step {
+            count,
+            pc,
+            halt,
+            writes: Vec::new(),
+        });
+    }
+
+    /// Records a write into the current step
+    pub fn record(&mut self, write: Write) {
+        if let Some(step) = self.steps.back_mut() {
+            step.writes.push(write);
+        }
+    }
+
+    /// Instruction count of the oldest step that can still be undone
+    pub fn oldest(&self) -> Option<u64> {
+        self.steps.front().map(|s| s.count)
+    }
+
+    /// Instruction count of the most recent step that wrote to `r`
+    pub fn last_register_write(&self, r: Register) -> Option<u64> {
+        self.last_write(|w| matches!(w, Write::Register(wr, _) if *wr == r))
+    }
+
+    /// Instruction count of the most recent step that wrote to `address`
+    pub fn last_memory_write(&self, address: usize) -> Option<u64> {
+        self.last_write(|w| matches!(w, Write::Memory(a, _) if *a == address))
+    }
+
+    fn last_write(&self, f: impl Fn(&Write) -> bool) -> Option<u64> {
+        self.steps
+            .iter()
+            .rev()
+            .find(|s| s.writes.iter().any(&f))
+            .map(|s| s.count)
+    }
+
+    fn pop(&mut self) -> Option<Step> {
+        self.steps.pop_back()
+    }
+}
+
+impl Machine {
+    /// Starts recording an undo log so that execution can be reversed
+    pub fn enable_history(&mut self) {
+        self.history = Some(History::new());
+    }
+
+    /// Undo log, if history is enabled
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Undoes the last executed instruction
+    /// Only the machine state is restored, side effects on the file system, stdin, the clock and
+    /// the console are not undone
+    pub fn step_back(&mut self) -> Result<(), String> {
+        let step = self
+            .history
+            .as_mut()
+            .ok_or("history is not enabled")?
+            .pop()
+            .ok_or("no steps left to undo")?;
+
+        for write in step.writes.into_iter().rev() {
+            match write {
+                Write::Register(r, v) => self.registers[r as usize] = v,
+                Write::FRegister(f, v) => self.fregisters[f as usize] = v,
+                Write::Memory(a, v) => self.memory[a] = v,
+                Write::Block(change) => self.heap.undo(change),
+                Write::Object { slot, old } => {
+                    self.objects.replace(slot, old);
+                }
+                Write::Allocated(n) => self.objects.allocated = n,
+                Write::Random(rng) => self.rng = rng,
+            }
+        }
+        self.registers[Register::PC as usize] = step.pc;
+        self.halt = step.halt;
+        self.instruction_count = step.count;
+        Ok(())
+    }
+
+    /// Runs backwards until just before the last instruction that wrote to `r`
+    /// Returns the instruction count of that instruction
+    pub fn run_back_to_register_write(&mut self, r: Register) -> Result<u64, String> {
+        let count = self
+            .history
+            .as_ref()
+            .ok_or("history is not enabled")?
+            .last_register_write(r)
+            .ok_or(format!("no recorded write to {:?}", r))?;
+        self.goto(count)?;
+        Ok(count)
+    }
+
+    /// Runs backwards until just before the last instruction that wrote to `address`
+    /// Returns the instruction count of that instruction
+    pub fn run_back_to_memory_write(&mut self, address: usize) -> Result<u64, String> {
+        let count = self
+            .history
+            .as_ref()
+            .ok_or("history is not enabled")?
+            .last_memory_write(address)
+            .ok_or(format!("no recorded write to 0x{:04X}", address))?;
+        self.goto(count)?;
+        Ok(count)
+    }
+
+    /// Moves to instruction count `count`
+    /// Goes backwards through the undo log, or forwards by executing instructions
+    pub fn goto(&mut self, count: u64) -> Result<(), String> {
+        if count < self.instruction_count {
+            let oldest = self
+                .history
+                .as_ref()
+                .ok_or("history is not enabled")?
+                .oldest()
+                .unwrap_or(self.instruction_count);
+            if count < oldest {
+                return Err(format!("instruction {} is no longer recorded", count));
+            }
+        }
+        while self.instruction_count > count {
+            self.step_back()?;
+        }
+        while self.instruction_count < count && !self.halt {
+            self.step()?;
+        }
+        Ok(())
+    }
+}
@@ -1,22 +1,70 @@
+mod args;
+pub mod asm;
+mod bitwise;
+pub mod clock;
+mod console;
+pub mod coverage;
 pub mod fetch;
+pub mod flags;
+pub mod fpu;
+pub mod fs;
+pub mod gc;
+pub mod heap;
+pub mod history;
+pub mod lines;
+mod memory;
+pub mod object;
 pub mod opcode;
+pub mod profile;
+pub mod program;
+pub mod random;
+mod signed;
+pub mod snapshot;
+pub mod symbols;
 pub mod syscall;
+mod wide;
 
+use std::io::{self, BufRead, BufReader};
+
+use clock::{Clock, SystemClock};
+use coverage::Coverage;
 use fetch::Fetch;
+use fpu::FRegister;
+use fs::FileSystem;
+use gc::ObjectHeap;
+use heap::Heap;
+use history::{History, Write};
 use opcode::OpCode;
+use profile::Profiler;
+use program::Program;
+use random::Rng;
 use syscall::Syscall;
 
 /// Register Enum
-#[derive(Debug, Copy, Clone)]
+/// - R0..R15: General purpose registers, `A`, `B`, `C` and `M` are aliases for R0..R3
+/// - SP, PC and FLAGS are special purpose
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Register {
-    /// First register
-    A,
-    /// Second register
-    B,
-    /// Third register
-    C,
-    /// Multipurpose register
-    M,
+    /// First register, `A`
+    R0,
+    /// Second register, `B`
+    R1,
+    /// Third register, `C`
+    R2,
+    /// Multipurpose register, `M`
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
     /// Stack pointer
     SP,
     /// Program counter
@@ -27,24 +75,89 @@ pub enum Register {
     RegisterCount,
 }
 
+impl Register {
+    /// First register
+    pub const A: Register = Register::R0;
+    /// Second register
+    pub const B: Register = Register::R1;
+    /// Third register
+    pub const C: Register = Register::R2;
+    /// Multipurpose register
+    pub const M: Register = Register::R3;
+
+    /// General purpose registers, in encoding order
+    pub const GENERAL: [Register; 16] = [
+        Register::R0,
+        Register::R1,
+        Register::R2,
+        Register::R3,
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::R7,
+        Register::R8,
+        Register::R9,
+        Register::R10,
+        Register::R11,
+        Register::R12,
+        Register::R13,
+        Register::R14,
+        Register::R15,
+    ];
+}
+
 /// Machine
 /// - Registers: Holds the registers
+/// - FRegisters: Holds the floating-point registers
 /// - Memory   : Holds the mutable data
 /// - Data     : Holds the immutable data
+/// - Heap     : Allocator for the heap region of memory
+/// - Objects  : Garbage-collected object heap
+/// - FS       : File descriptor table for file-system syscalls
+/// - Clock    : Time source for the time syscalls
+/// - RNG      : Pseudo-random number generator for the random syscalls
+/// - Stdin    : Input of the console syscalls
 /// - Halt     : Should the program halt
 /// - Debug    : Prints debug information
+/// - FPU      : Is the floating-point unit available
 ///     - TODO: have all debug info written to a file rather than to stdout as to not be confused with output of the program
+/// - Instruction count: How many instructions have been executed
+/// - Version  : Bytecode version of the loaded program, decides how registers are encoded
+/// - History  : Undo log for reverse execution, if enabled
+/// - Profiler : Execution counts per PC, if enabled
+/// - Coverage : Executed instructions, if enabled
 pub struct Machine {
     registers: [u16; Self::REGISTER_COUNT],
+    fregisters: [f64; Self::FREGISTER_COUNT],
     memory: [u8; Self::MEMORY_LENGTH],
     data: [u8; Self::DATA_LENGTH],
+    heap: Heap,
+    objects: ObjectHeap,
+    fs: FileSystem,
+    clock: Box<dyn Clock>,
+    rng: Rng,
+    stdin: Box<dyn BufRead>,
     pub halt: bool,
     pub debug: bool,
+    fpu: bool,
+    instruction_count: u64,
+    version: u8,
+    history: Option<History>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Machine {
     /// How many registers does this machine have?
     pub const REGISTER_COUNT: usize = Register::RegisterCount as usize;
+    /// How many floating-point registers does this machine have?
+    pub const FREGISTER_COUNT: usize = FRegister::FRegisterCount as usize;
     /// Mutable memory length
     pub const MEMORY_LENGTH: usize = 4096;
     /// Immutable memory length
@@ -54,10 +167,23 @@ impl Machine {
     pub fn new() -> Self {
         Self {
             registers: [0; Self::REGISTER_COUNT],
+            fregisters: [0.0; Self::FREGISTER_COUNT],
             memory: [0; Self::MEMORY_LENGTH],
             data: [0; Self::DATA_LENGTH],
+            heap: Heap::new(),
+            objects: ObjectHeap::new(),
+            fs: FileSystem::new(),
+            clock: Box::new(SystemClock::new()),
+            rng: Rng::from_time(),
+            stdin: Box::new(BufReader::new(io::stdin())),
             halt: false,
             debug: false,
+            fpu: true,
+            instruction_count: 0,
+            version: Program::VERSION,
+            history: None,
+            profiler: None,
+            coverage: None,
         }
     }
 
@@ -77,11 +203,36 @@ impl Machine {
         self.data[..new_data.len()].copy_from_slice(new_data);
     }
 
+    /// Loads an assembled program into memory and data
+    pub fn load(&mut self, program: &Program) {
+        self.set_memory(&program.memory);
+        self.set_data(&program.data);
+        self.version = program.version;
+    }
+
     /// Enables debug information for the machine
     pub fn enable_debug(&mut self) {
         self.debug = true;
     }
 
+    /// How many instructions have been executed
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// Gets the value of a register
+    pub fn register(&self, r: Register) -> u16 {
+        self.registers[r as usize]
+    }
+
+    /// Writes to a register, recording the old value if history is enabled
+    fn write_register(&mut self, r: Register, v: u16) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(Write::Register(r, self.registers[r as usize]));
+        }
+        self.registers[r as usize] = v;
+    }
+
     /// Gets a value from memory at program counter
     fn fetch<'a, T>(&mut self) -> Result<T, String>
     where
@@ -89,25 +240,65 @@ impl Machine {
     {
         let pc = self.registers[Register::PC as usize] as usize;
         let d_point = self.memory[pc];
-        let v: T = T::try_from(d_point).map_err(|_| format!("could not fetch"))?;
+        let v: T = T::decode(d_point, self.version).ok_or("could not fetch")?;
         self.registers[Register::PC as usize] += 1;
         Ok(v)
     }
 
+    /// Fetches the last operand of an instruction, either an immediate or the value of a register
+    fn fetch_operand(&mut self, register_form: bool) -> Result<u16, String> {
+        if register_form {
+            let r: Register = self.fetch()?;
+            Ok(self.register(r))
+        } else {
+            self.fetch()
+        }
+    }
+
+    /// Like [`Machine::fetch_operand`], but immediates are sign-extended from a byte
+    fn fetch_signed_operand(&mut self, register_form: bool) -> Result<i16, String> {
+        if register_form {
+            let r: Register = self.fetch()?;
+            Ok(self.register(r) as i16)
+        } else {
+            let v: u8 = self.fetch()?;
+            Ok(v as i8 as i16)
+        }
+    }
+
     /// Prints the Machine's state
     pub fn print_state(&self) {
-        let [a, b, c, m, sp, pc, flags] = self.registers;
-        println!("|-----------------------------------------|");
-        println!("| A  {a:04X} | B  {b:04X} | C     {c:04X} | M {m:04X} |");
-        println!("| SP {sp:04X} | PC {pc:04X} | FLAGS {flags:04X} |");
+        let [a, b, c, m, general @ .., sp, pc, flags] = self.registers;
+        println!("|-------------------------------------------|");
+        println!("| A   {a:04X} | B   {b:04X} | C   {c:04X} | M   {m:04X} |");
+        for (row, values) in general.chunks(4).enumerate() {
+            print!("|");
+            for (i, v) in values.iter().enumerate() {
+                let name = format!("R{}", 4 + row * 4 + i);
+                print!(" {name:<3} {v:04X} |");
+            }
+            println!();
+        }
+        println!("| SP  {sp:04X} | PC  {pc:04X} | FLAGS {flags:04X} |");
+        if self.fpu {
+            for (row, values) in self.fregisters.chunks(4).enumerate() {
+                print!("|");
+                for (i, v) in values.iter().enumerate() {
+                    let name = format!("F{}", row * 4 + i);
+                    print!(" {name} {v:<10.4} |");
+                }
+                println!();
+            }
+        }
     }
 
     /// Takes a `step` in the machine, increment the program counter by one and take an action
     /// The step function is seperated into the following main steps
     /// 1. Check if debug is set, if so, print state
     /// 2. Check out of bounds error: if the program counter is larger than the memory length, halt
-    /// 3. Get current opcode and act accordingly
-    /// 4. If that opcode fails, return.
+    /// 3. Record the step in the undo log, if history is enabled
+    /// 4. Get current opcode and act accordingly
+    /// 5. If that opcode fails, return.
     pub fn step(&mut self) -> Result<(), String> {
         if self.debug {
             self.print_state();
@@ -115,22 +306,69 @@ impl Machine {
 
         if self.registers[Register::PC as usize] as usize > self.memory.len() {
             self.halt = true;
-            return Err(format!("Out of bounds"));
+            return Err("Out of bounds".to_string());
+        }
+
+        let pc = self.registers[Register::PC as usize];
+        if let Some(history) = self.history.as_mut() {
+            history.begin(self.instruction_count, pc, self.halt);
         }
+        self.instruction_count += 1;
 
         let op: OpCode = self.fetch()?;
 
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(pc, op);
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(pc);
+        }
+
         match op {
             OpCode::HALT => self.handle_halt(),
             OpCode::NOP => self.handle_nop(),
             OpCode::SYSCALL => self.handle_syscall()?,
-            OpCode::ADD => self.handle_add()?,
-            OpCode::SUB => self.handle_sub()?,
-            OpCode::MUL => self.handle_mul()?,
-            OpCode::DIV => self.handle_div()?,
+            OpCode::ADD => self.handle_add(false)?,
+            OpCode::ADDR => self.handle_add(true)?,
+            OpCode::SUB => self.handle_sub(false)?,
+            OpCode::SUBR => self.handle_sub(true)?,
+            OpCode::MUL => self.handle_mul(false)?,
+            OpCode::MULR => self.handle_mul(true)?,
+            OpCode::DIV => self.handle_div(false)?,
+            OpCode::DIVR => self.handle_div(true)?,
+            OpCode::MOD | OpCode::IMUL | OpCode::IDIV | OpCode::IMOD => self.handle_signed(op)?,
+            OpCode::MODR | OpCode::IMULR | OpCode::IDIVR | OpCode::IMODR => {
+                self.handle_signed(op)?
+            }
+            OpCode::ADC | OpCode::SBB | OpCode::ADCR | OpCode::SBBR => self.handle_carry(op)?,
+            OpCode::MULW | OpCode::IMULW => self.handle_mulw(op)?,
+            OpCode::FADD | OpCode::FSUB | OpCode::FMUL | OpCode::FDIV => self.handle_float(op)?,
+            OpCode::FSQRT | OpCode::FMOV | OpCode::ITOF | OpCode::FTOI => self.handle_float(op)?,
+            OpCode::FCMP | OpCode::FLD => self.handle_float(op)?,
+            OpCode::MEMCPY | OpCode::MEMSET | OpCode::MEMCMP | OpCode::DATACPY => {
+                self.handle_memory(op)?
+            }
+            OpCode::NEWSTR | OpCode::NEWARR | OpCode::NEWREC => self.handle_object(op)?,
+            OpCode::GETF | OpCode::SETF | OpCode::OLEN | OpCode::OTYPE | OpCode::GC => {
+                self.handle_object(op)?
+            }
+            OpCode::NEG => self.handle_neg()?,
+            OpCode::CMP | OpCode::CMPR | OpCode::ICMP | OpCode::ICMPR => self.handle_cmp(op)?,
+            OpCode::SEXT | OpCode::ZEXT => self.handle_extend(op)?,
             OpCode::POP => self.handle_pop()?,
             OpCode::PUSH => self.handle_push()?,
             OpCode::SWAP => self.handle_swap()?,
+            OpCode::AND | OpCode::OR | OpCode::XOR => self.handle_logic(op)?,
+            OpCode::ANDR | OpCode::ORR | OpCode::XORR => self.handle_logic(op)?,
+            OpCode::NOT => self.handle_not()?,
+            OpCode::SHL | OpCode::SHR | OpCode::SAR | OpCode::ROL | OpCode::ROR => {
+                self.handle_shift(op)?
+            }
+            OpCode::SHLR | OpCode::SHRR | OpCode::SARR | OpCode::ROLR | OpCode::RORR => {
+                self.handle_shift(op)?
+            }
+            OpCode::BIT | OpCode::BSET | OpCode::BCLR => self.handle_bit(op)?,
+            OpCode::BITR | OpCode::BSETR | OpCode::BCLRR => self.handle_bit(op)?,
         }
         Ok(())
     }
@@ -150,56 +388,67 @@ impl Machine {
         }
     }
 
-    /// Take in a register and two values
+    /// Take in a register and two values, or three registers for `ADDR`
     /// Adds the values together and puts it into specified register
-    fn handle_add(&mut self) -> Result<(), String> {
+    /// Sets FLAGS: Z and S from the result, C on unsigned overflow, O on signed overflow
+    fn handle_add(&mut self, register_form: bool) -> Result<(), String> {
         let r: Register = self.fetch()?;
-        let a: u16 = self.fetch()?;
-        let b: u16 = self.fetch()?;
-        let result = a + b;
-        self.registers[r as usize] = result;
+        let a = self.fetch_operand(register_form)?;
+        let b = self.fetch_operand(register_form)?;
+        let (result, carry) = a.overflowing_add(b);
+        let overflow = (a as i16).overflowing_add(b as i16).1;
+        self.write_register(r, result);
+        self.set_flags(result, carry, overflow);
         if self.debug {
             println!("| ADD: Reg {:?} 0x{:X}, 0x{:X} -> 0x{:X}", r, a, b, result);
         }
         Ok(())
     }
 
-    /// Take in a register and two values
+    /// Take in a register and two values, or three registers for `SUBR`
     /// Subs the values together and puts it into specified register
-    fn handle_sub(&mut self) -> Result<(), String> {
+    /// Sets FLAGS: Z and S from the result, C on borrow, O on signed overflow
+    fn handle_sub(&mut self, register_form: bool) -> Result<(), String> {
         let r: Register = self.fetch()?;
-        let a: u16 = self.fetch()?;
-        let b: u16 = self.fetch()?;
-        let result = a - b;
-        self.registers[r as usize] = result;
+        let a = self.fetch_operand(register_form)?;
+        let b = self.fetch_operand(register_form)?;
+        let (result, carry) = a.overflowing_sub(b);
+        let overflow = (a as i16).overflowing_sub(b as i16).1;
+        self.write_register(r, result);
+        self.set_flags(result, carry, overflow);
         if self.debug {
             println!("| SUB: Reg {:?} 0x{:X}, 0x{:X} -> 0x{:X}", r, a, b, result);
         }
         Ok(())
     }
 
-    /// Take in a register and two values
+    /// Take in a register and two values, or three registers for `MULR`
     /// Multiplies the values together and puts it into specified register
-    fn handle_mul(&mut self) -> Result<(), String> {
+    /// Sets FLAGS: Z and S from the result, C and O when the result does not fit
+    fn handle_mul(&mut self, register_form: bool) -> Result<(), String> {
         let r: Register = self.fetch()?;
-        let a: u16 = self.fetch()?;
-        let b: u16 = self.fetch()?;
-        let result = a * b;
-        self.registers[r as usize] = result;
+        let a = self.fetch_operand(register_form)?;
+        let b = self.fetch_operand(register_form)?;
+        let (result, carry) = a.overflowing_mul(b);
+        let overflow = (a as i16).overflowing_mul(b as i16).1;
+        self.write_register(r, result);
+        self.set_flags(result, carry, overflow);
         if self.debug {
             println!("| MUL: Reg {:?} 0x{:X}, 0x{:X} -> 0x{:X}", r, a, b, result);
         }
         Ok(())
     }
 
-    /// Take in a register and two values
+    /// Take in a register and two values, or three registers for `DIVR`
     /// Divides the values together and puts it into specified register
-    fn handle_div(&mut self) -> Result<(), String> {
+    /// Sets FLAGS: Z and S from the result, fails on division by zero
+    fn handle_div(&mut self, register_form: bool) -> Result<(), String> {
         let r: Register = self.fetch()?;
-        let a: u16 = self.fetch()?;
-        let b: u16 = self.fetch()?;
-        let result = a / b;
-        self.registers[r as usize] = result;
+        let a = self.fetch_operand(register_form)?;
+        let b = self.fetch_operand(register_form)?;
+        let result = a.checked_div(b).ok_or("division by zero")?;
+        self.write_register(r, result);
+        self.set_flags(result, false, false);
         if self.debug {
             println!("| DIV: Reg {:?} 0x{:X}, 0x{:X} -> 0x{:X}", r, a, b, result);
         }
@@ -209,7 +458,7 @@ impl Machine {
     /// Pop what ever the value was in SP into `r`
     fn handle_pop(&mut self) -> Result<(), String> {
         let r: Register = self.fetch()?;
-        self.registers[r as usize] = self.registers[Register::SP as usize];
+        self.write_register(r, self.registers[Register::SP as usize]);
         if self.debug {
             println!("| POP: Reg {:?}", r);
         }
@@ -219,7 +468,7 @@ impl Machine {
     /// Push what ever the value was in `r` into SP
     fn handle_push(&mut self) -> Result<(), String> {
         let r: Register = self.fetch()?;
-        self.registers[Register::SP as usize] = self.registers[r as usize];
+        self.write_register(Register::SP, self.registers[r as usize]);
         if self.debug {
             println!("| POP: PUSH {:?}", r);
         }
@@ -234,12 +483,10 @@ impl Machine {
         let mut v1 = self.registers[r1 as usize];
         let mut v2 = self.registers[r2 as usize];
 
-        let temp = v2;
-        v2 = v1;
-        v1 = temp;
+        std::mem::swap(&mut v1, &mut v2);
 
-        self.registers[r1 as usize] = v1;
-        self.registers[r2 as usize] = v2;
+        self.write_register(r1, v1);
+        self.write_register(r2, v2);
         if self.debug {
             println!("| SWAP: Reg {:?} ({}) Reg {:?} ({})", r1, v1, r2, v2);
         }
@@ -255,3 +502,26 @@ impl Machine {
         Ok(())
     }
 }
+
+/// Helpers shared by the tests of every module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::{assemble, Options};
+
+    /// Machine with `source` assembled and loaded
+    pub(crate) fn machine(source: &str) -> Machine {
+        let program = assemble(source, &Options::default()).unwrap();
+        let mut m = Machine::new();
+        m.load(&program);
+        m
+    }
+
+    /// Runs until the machine halts or fails
+    pub(crate) fn run(m: &mut Machine) -> Result<(), String> {
+        while !m.halt {
+            m.step()?;
+        }
+        Ok(())
+    }
+}
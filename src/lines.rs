@@ -0,0 +1,69 @@
+use std::fmt;
+
+/// A line in an assembly source file
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+}
+
+impl fmt::Display for SourceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+impl TryFrom<&str> for SourceLine {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (file, line) = value
+            .rsplit_once(':')
+            .ok_or(format!("invalid source line `{}`", value))?;
+        let line = line
+            .parse()
+            .map_err(|_| format!("invalid line number in `{}`", value))?;
+        Ok(Self {
+            file: file.to_string(),
+            line,
+        })
+    }
+}
+
+/// Maps the address of every instruction to the source line it was assembled from
+#[derive(Debug, Default, Clone)]
+pub struct LineTable {
+    lines: Vec<(u16, SourceLine)>,
+}
+
+impl LineTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the instruction at `address` comes from `line`
+    pub fn insert(&mut self, address: u16, line: SourceLine) {
+        let at = self.lines.partition_point(|(a, _)| *a < address);
+        if self.lines.get(at).is_some_and(|(a, _)| *a == address) {
+            self.lines[at].1 = line;
+        } else {
+            self.lines.insert(at, (address, line));
+        }
+    }
+
+    /// Source line of the instruction starting at `address`
+    pub fn get(&self, address: u16) -> Option<&SourceLine> {
+        self.lines
+            .binary_search_by_key(&address, |(a, _)| *a)
+            .ok()
+            .map(|i| &self.lines[i].1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Iterates over `(address, line)` in address order
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &SourceLine)> {
+        self.lines.iter().map(|(a, l)| (*a, l))
+    }
+}
@@ -0,0 +1,162 @@
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::flags::Flag;
+use crate::history::Write;
+use crate::opcode::OpCode;
+use crate::{Machine, Register};
+
+/// Block instructions on memory, every operand is a register
+/// - `MEMCPY dst src len`: copy `len` bytes from `src` to `dst`, overlapping ranges are allowed
+/// - `MEMSET dst val len`: fill `len` bytes at `dst` with the low byte of `val`
+/// - `MEMCMP a b len`    : compare `len` bytes at `a` and `b`
+/// - `DATACPY dst src len`: copy `len` bytes from `src` in data to `dst` in memory
+///
+/// FLAGS
+/// - `MEMCMP`: Z when equal, C when the first differing byte at `a` is less than the one at `b`
+impl Machine {
+    /// Writes to mutable memory, recording the old values if history is enabled
+    pub(crate) fn write_memory(&mut self, address: usize, bytes: &[u8]) -> Result<(), String> {
+        let range = self.memory_range(address, bytes.len())?;
+        if let Some(history) = self.history.as_mut() {
+            for (i, &old) in self.memory[range.clone()].iter().enumerate() {
+                history.record(Write::Memory(address + i, old));
+            }
+        }
+        self.memory[range].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Reads from mutable memory
+    pub fn read_memory(&self, address: usize, len: usize) -> Result<&[u8], String> {
+        let range = self.memory_range(address, len)?;
+        Ok(&self.memory[range])
+    }
+
+    /// Reads the bytes of a null terminated string from memory, without the null
+    pub(crate) fn read_cstr(&self, address: usize) -> Result<&[u8], String> {
+        let len = self
+            .memory
+            .get(address..)
+            .and_then(|m| m.iter().position(|&b| b == 0))
+            .ok_or(format!("no null terminated string at 0x{:04X}", address))?;
+        self.read_memory(address, len)
+    }
+
+    /// Bounds checked range of `len` bytes of memory starting at `address`
+    /// With heap checking on, accesses to the heap must stay inside live blocks
+    pub(crate) fn memory_range(&self, address: usize, len: usize) -> Result<Range<usize>, String> {
+        if self.heap.checked {
+            self.heap.check_access(address, len)?;
+        }
+        address
+            .checked_add(len)
+            .filter(|&end| end <= self.memory.len())
+            .map(|end| address..end)
+            .ok_or(format!(
+                "memory range 0x{:04X}+{} out of bounds",
+                address, len
+            ))
+    }
+
+    /// MEMCPY, MEMSET, MEMCMP and DATACPY
+    pub(crate) fn handle_memory(&mut self, op: OpCode) -> Result<(), String> {
+        let a: Register = self.fetch()?;
+        let b: Register = self.fetch()?;
+        let len: Register = self.fetch()?;
+        let a = self.register(a) as usize;
+        let b = self.register(b) as usize;
+        let len = self.register(len) as usize;
+
+        match op {
+            OpCode::MEMCPY => {
+                let src = self.read_memory(b, len)?.to_vec();
+                self.write_memory(a, &src)?;
+            }
+            OpCode::MEMSET => self.write_memory(a, &vec![b as u8; len])?,
+            OpCode::MEMCMP => {
+                let ordering = self.read_memory(a, len)?.cmp(self.read_memory(b, len)?);
+                self.set_flag(Flag::Zero, ordering == Ordering::Equal);
+                self.set_flag(Flag::Carry, ordering == Ordering::Less);
+            }
+            OpCode::DATACPY => {
+                let src = self
+                    .data
+                    .get(b..b + len)
+                    .ok_or(format!("data range 0x{:04X}+{} out of bounds", b, len))?
+                    .to_vec();
+                self.write_memory(a, &src)?;
+            }
+            _ => unreachable!("{:?} is not a memory opcode", op),
+        }
+        if self.debug {
+            println!("| {:?}: 0x{:04X}, 0x{:04X}, {} bytes", op, a, b, len);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{machine, run};
+
+    /// Runs `OP A B C` with those registers set and `1 2 3 4 5` at 0x100
+    fn block(op: &str, a: u16, b: u16, len: u16) -> Result<Machine, String> {
+        let mut m = machine(&format!("{} A B C\nHALT", op));
+        m.memory[0x100..0x105].copy_from_slice(&[1, 2, 3, 4, 5]);
+        for (r, v) in [(Register::A, a), (Register::B, b), (Register::C, len)] {
+            m.registers[r as usize] = v;
+        }
+        run(&mut m)?;
+        Ok(m)
+    }
+
+    #[test]
+    fn overlapping_copies() {
+        let m = block("MEMCPY", 0x101, 0x100, 4).unwrap();
+        assert_eq!(m.memory[0x100..0x105], [1, 1, 2, 3, 4]);
+        let m = block("MEMCPY", 0x100, 0x101, 4).unwrap();
+        assert_eq!(m.memory[0x100..0x105], [2, 3, 4, 5, 5]);
+        let m = block("MEMSET", 0x101, 0x1FF, 3).unwrap();
+        assert_eq!(m.memory[0x100..0x105], [1, 0xFF, 0xFF, 0xFF, 5]);
+    }
+
+    #[test]
+    fn compares() {
+        let flags = |m: Machine| [m.flag(Flag::Zero), m.flag(Flag::Carry)];
+        assert_eq!(
+            flags(block("MEMCMP", 0x100, 0x100, 5).unwrap()),
+            [true, false]
+        );
+        assert_eq!(
+            flags(block("MEMCMP", 0x100, 0x101, 2).unwrap()),
+            [false, true]
+        );
+        assert_eq!(
+            flags(block("MEMCMP", 0x101, 0x100, 2).unwrap()),
+            [false, false]
+        );
+        // Nothing to compare is equal
+        assert_eq!(
+            flags(block("MEMCMP", 0x100, 0x101, 0).unwrap()),
+            [true, false]
+        );
+    }
+
+    #[test]
+    fn bounds() {
+        assert_eq!(
+            block("MEMCPY", 0xFFF, 0x100, 2).err().unwrap(),
+            "memory range 0x0FFF+2 out of bounds"
+        );
+        assert_eq!(
+            block("MEMSET", 0x100, 0, 0xF01).err().unwrap(),
+            "memory range 0x0100+3841 out of bounds"
+        );
+        assert_eq!(
+            block("DATACPY", 0x100, 0x3FC, 8).err().unwrap(),
+            "data range 0x03FC+8 out of bounds"
+        );
+    }
+}
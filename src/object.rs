@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+
+use crate::program::Program;
+
+/// What the operand at a relocated address is patched with
+/// - Code  : Its value is an address in the object's code, the object's code base is added
+/// - Data  : Its value is an address in the object's data, the object's data base is added
+/// - Symbol: The final address of the symbol, usually imported from another object
+#[derive(Debug, Clone, PartialEq)]
+pub enum Relocation {
+    Code,
+    Data,
+    Symbol(String),
+}
+
+/// A relocatable object, as stored in a `.obj` file
+/// - Name       : File the object was read from, for diagnostics
+/// - Program    : Code and data with addresses starting at 0, every label and source line
+/// - Exports    : Labels other objects can import
+/// - Imports    : Symbols this object needs from other objects
+/// - Relocations: Operands to patch once the object's place is known
+///
+/// `.obj` layout, a `.proj` with more sections, objects can be concatenated into a library
+/// ```text
+/// [[OBJECT 1]]
+/// [[VERSION 2]]
+/// ..                      ; like a `.proj`
+/// [[EXPORTS]]
+/// print                   ; one name per line
+/// [[IMPORTS]]
+/// newline                 ; one name per line
+/// [[RELOCATIONS]]
+/// 0x0005 code             ; one `address kind` pair per line, kind is `code`, `data` or `symbol name`
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+    pub name: String,
+    pub program: Program,
+    pub exports: Vec<String>,
+    pub imports: Vec<String>,
+    pub relocations: Vec<(u16, Relocation)>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Section {
+    Program,
+    Exports,
+    Imports,
+    Relocations,
+}
+
+impl Object {
+    /// Object format version
+    pub const VERSION: u8 = 1;
+
+    /// Reads every object of a `.obj` file or library
+    pub fn load(file_path: &str) -> Result<Vec<Self>, String> {
+        let source =
+            fs::read_to_string(file_path).map_err(|_| format!("could not read {}", file_path))?;
+        let mut objects = Self::parse_many(&source).map_err(|e| format!("{}: {}", file_path, e))?;
+        for o in objects.iter_mut() {
+            o.name = file_path.to_string();
+        }
+        Ok(objects)
+    }
+
+    /// Parses concatenated objects
+    pub fn parse_many(source: &str) -> Result<Vec<Self>, String> {
+        let mut chunks: Vec<Vec<&str>> = Vec::new();
+        for line in source.lines() {
+            if line.trim().starts_with("[[OBJECT ") || chunks.is_empty() {
+                chunks.push(Vec::new());
+            }
+            chunks.last_mut().unwrap().push(line);
+        }
+        chunks
+            .iter()
+            .map(|lines| Self::parse(&lines.join("\n")))
+            .collect()
+    }
+
+    /// Parses a single object
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut object = Self::default();
+        let mut program = Vec::new();
+        let mut section = Section::Program;
+        let mut lines = source.lines().map(str::trim).filter(|l| !l.is_empty());
+
+        let header = lines.next().ok_or("empty object")?;
+        let version = header
+            .strip_prefix("[[OBJECT ")
+            .and_then(|v| v.strip_suffix("]]"))
+            .ok_or("missing [[OBJECT]] header")?;
+        if version.trim() != Self::VERSION.to_string() {
+            return Err(format!("unsupported object version `{}`", version));
+        }
+
+        for line in lines {
+            match line {
+                "[[EXPORTS]]" => section = Section::Exports,
+                "[[IMPORTS]]" => section = Section::Imports,
+                "[[RELOCATIONS]]" => section = Section::Relocations,
+                _ if line.starts_with("[[") => {
+                    section = Section::Program;
+                    program.push(line);
+                }
+                _ => match section {
+                    Section::Program => program.push(line),
+                    Section::Exports => object.exports.push(line.to_string()),
+                    Section::Imports => object.imports.push(line.to_string()),
+                    Section::Relocations => {
+                        let (address, kind) = line
+                            .split_once(' ')
+                            .ok_or(format!("invalid relocation `{}`", line))?;
+                        let address = u16::from_str_radix(address.trim_start_matches("0x"), 16)
+                            .map_err(|_| format!("invalid relocation `{}`", line))?;
+                        let relocation = match kind.trim() {
+                            "code" => Relocation::Code,
+                            "data" => Relocation::Data,
+                            kind => match kind.strip_prefix("symbol ") {
+                                Some(name) => Relocation::Symbol(name.trim().to_string()),
+                                None => return Err(format!("invalid relocation `{}`", line)),
+                            },
+                        };
+                        object.relocations.push((address, relocation));
+                    }
+                },
+            }
+        }
+
+        object.program = Program::parse(&program.join("\n"))?;
+        Ok(object)
+    }
+
+    /// Address of an exported symbol inside this object
+    fn export(&self, name: &str) -> Option<u16> {
+        if self.exports.iter().any(|e| e == name) {
+            self.program.symbols.get(name)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[[OBJECT {}]]", Self::VERSION)?;
+        let program = self.program.to_string();
+        write!(f, "{}", program)?;
+        if !program.ends_with('\n') {
+            writeln!(f)?;
+        }
+        writeln!(f, "[[EXPORTS]]")?;
+        for name in &self.exports {
+            writeln!(f, "{name}")?;
+        }
+        writeln!(f, "[[IMPORTS]]")?;
+        for name in &self.imports {
+            writeln!(f, "{name}")?;
+        }
+        writeln!(f, "[[RELOCATIONS]]")?;
+        for (address, relocation) in &self.relocations {
+            match relocation {
+                Relocation::Code => writeln!(f, "0x{address:04X} code")?,
+                Relocation::Data => writeln!(f, "0x{address:04X} data")?,
+                Relocation::Symbol(name) => writeln!(f, "0x{address:04X} symbol {name}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Links objects into a program
+/// Every object is kept, in order, so the first one holds the entry point at address 0.
+/// Library members are only added when they export a symbol that is still undefined
+pub fn link(mut objects: Vec<Object>, libraries: Vec<Object>) -> Result<Program, String> {
+    // Pull in library members until nothing new is needed
+    let mut members: Vec<Option<Object>> = libraries.into_iter().map(Some).collect();
+    loop {
+        let undefined: Vec<&String> = objects
+            .iter()
+            .flat_map(|o| &o.imports)
+            .filter(|name| !objects.iter().any(|o| o.export(name).is_some()))
+            .collect();
+        let next = members.iter().position(|m| {
+            m.as_ref()
+                .is_some_and(|m| undefined.iter().any(|name| m.export(name).is_some()))
+        });
+        match next {
+            Some(i) => objects.push(members[i].take().unwrap()),
+            None => break,
+        }
+    }
+
+    // Layout, code and data of every object one after another
+    let mut program = Program::default();
+    let mut bases = Vec::new();
+    for o in &objects {
+        if o.program.version != Program::VERSION {
+            return Err(format!(
+                "{}: bytecode version {} does not match {}",
+                o.name,
+                o.program.version,
+                Program::VERSION
+            ));
+        }
+        bases.push((program.memory.len(), program.data.len()));
+        program.memory.extend_from_slice(&o.program.memory);
+        program.data.extend_from_slice(&o.program.data);
+    }
+
+    // Exported symbols
+    let mut globals: HashMap<&str, (usize, &str)> = HashMap::new();
+    for (o, &(code, _)) in objects.iter().zip(&bases) {
+        for name in &o.exports {
+            let address = o.export(name).ok_or(format!(
+                "{}: exported symbol `{}` is not defined",
+                o.name, name
+            ))?;
+            if let Some((_, other)) = globals.insert(name, (code + address as usize, &o.name)) {
+                return Err(format!(
+                    "duplicate symbol `{}` in {} and {}",
+                    name, other, o.name
+                ));
+            }
+        }
+    }
+
+    // Patch every relocated operand
+    for (o, &(code, data)) in objects.iter().zip(&bases) {
+        for (address, relocation) in &o.relocations {
+            let at = code + *address as usize;
+            let old = *program.memory.get(at).ok_or(format!(
+                "{}: relocation at 0x{:04X} is outside of its code",
+                o.name, address
+            ))? as usize;
+            let value = match relocation {
+                Relocation::Code => code + old,
+                Relocation::Data => data + old,
+                Relocation::Symbol(name) => match globals.get(name.as_str()) {
+                    Some(&(address, _)) => address,
+                    None if o.imports.contains(name) => {
+                        return Err(format!(
+                            "undefined symbol `{}` referenced by {}",
+                            name, o.name
+                        ))
+                    }
+                    None => {
+                        return Err(format!(
+                            "{}: `{}` is neither imported nor exported",
+                            o.name, name
+                        ))
+                    }
+                },
+            };
+            if value > u8::MAX as usize {
+                return Err(format!(
+                    "{}: relocated address 0x{:04X} at 0x{:04X} does not fit in an operand",
+                    o.name, value, at
+                ));
+            }
+            program.memory[at] = value as u8;
+        }
+    }
+
+    // Symbols and source lines, exported names win over local ones
+    for (o, &(code, _)) in objects.iter().zip(&bases) {
+        for name in &o.exports {
+            if let Some(address) = o.export(name) {
+                program
+                    .symbols
+                    .insert(name, (code + address as usize) as u16);
+            }
+        }
+    }
+    for (o, &(code, _)) in objects.iter().zip(&bases) {
+        for (address, name) in o.program.symbols.iter() {
+            if program.symbols.get(name).is_none() {
+                program
+                    .symbols
+                    .insert(name, (code + address as usize) as u16);
+            }
+        }
+        for (address, source) in o.program.lines.iter() {
+            program
+                .lines
+                .insert((code + address as usize) as u16, source.clone());
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::{self, Options};
+
+    fn object(name: &str, source: &str) -> Object {
+        let options = Options {
+            file: name.to_string(),
+            ..Options::default()
+        };
+        asm::assemble_object(source, &options).unwrap()
+    }
+
+    fn greet() -> Object {
+        object("greet.asm", "!global greet\ngreet:\nADD B $2 $0\nHALT")
+    }
+
+    fn main() -> Object {
+        object("main.asm", "!extern greet\nADD A greet $0\nHALT")
+    }
+
+    #[test]
+    fn library_members() {
+        let unused = object("unused.asm", "!global unused\nunused:\nHALT");
+        let newline = object("newline.asm", "!global newline\nnewline:\nHALT");
+        let greet = object(
+            "greet.asm",
+            "!global greet\n!extern newline\ngreet:\nADD B newline $0\nHALT",
+        );
+        let program = link(vec![main()], vec![unused, newline, greet]).unwrap();
+        // main, then greet, then newline which greet needs, never unused
+        assert_eq!(program.memory.len(), 5 + 5 + 1);
+        assert_eq!(program.memory[2], 5);
+        assert_eq!(program.memory[7], 10);
+        assert_eq!(program.symbols.get("newline"), Some(10));
+        assert_eq!(program.symbols.get("unused"), None);
+    }
+
+    #[test]
+    fn duplicate_symbols() {
+        let other = object("other.asm", "!global greet\ngreet:\nHALT");
+        assert_eq!(
+            link(vec![main(), greet(), other], Vec::new()).unwrap_err(),
+            "duplicate symbol `greet` in greet.asm and other.asm"
+        );
+    }
+
+    #[test]
+    fn undefined_imports() {
+        assert_eq!(
+            link(vec![main()], Vec::new()).unwrap_err(),
+            "undefined symbol `greet` referenced by main.asm"
+        );
+    }
+
+    #[test]
+    fn relocation_out_of_range() {
+        let padding = object("padding.asm", "!rept 100\nADD A $1 $2\n!endr");
+        assert_eq!(
+            link(vec![main(), padding, greet()], Vec::new()).unwrap_err(),
+            "main.asm: relocated address 0x0195 at 0x0002 does not fit in an operand"
+        );
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let source = "!global start\n!extern greet\nstart:\nADD A greet $0\nADD B text $1.5\nHALT\n[[DATA]]\n.label text\nHi";
+        let o = object("start.asm", source);
+        let parsed = Object::parse(&o.to_string()).unwrap();
+        assert_eq!(parsed.to_string(), o.to_string());
+        assert_eq!(parsed.exports, ["start"]);
+        assert_eq!(parsed.imports, ["greet"]);
+        assert_eq!(parsed.relocations.len(), 3);
+        assert_eq!(parsed.relocations, o.relocations);
+        assert_eq!(parsed.program.memory, o.program.memory);
+        assert_eq!(parsed.program.data, o.program.data);
+    }
+}
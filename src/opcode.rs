@@ -1,6 +1,6 @@
 macro_rules! generate_opcodes {
     ($($name:ident = $v:expr)*) => {
-        #[derive(Debug, PartialEq, Copy, Clone)]
+        #[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
         #[repr(u8)]
         pub enum OpCode {
             $(
@@ -41,8 +41,85 @@ generate_opcodes! {
     SUB = 0x51
     MUL = 0x52
     DIV = 0x53
+    MOD = 0x54
+    IMUL = 0x55
+    IDIV = 0x56
+    IMOD = 0x57
+    NEG = 0x58
+    CMP = 0x59
+    ICMP = 0x5A
+    SEXT = 0x5B
+    ZEXT = 0x5C
+    ADC = 0x5D
+    SBB = 0x5E
 
     PUSH = 0x60
     POP = 0x61
     SWAP = 0x62
+
+    AND = 0x70
+    OR = 0x71
+    XOR = 0x72
+    NOT = 0x73
+    SHL = 0x74
+    SHR = 0x75
+    SAR = 0x76
+    ROL = 0x77
+    ROR = 0x78
+    BIT = 0x79
+    BSET = 0x7A
+    BCLR = 0x7B
+
+    ANDR = 0x80
+    ORR = 0x81
+    XORR = 0x82
+    SHLR = 0x84
+    SHRR = 0x85
+    SARR = 0x86
+    ROLR = 0x87
+    RORR = 0x88
+    BITR = 0x89
+    BSETR = 0x8A
+    BCLRR = 0x8B
+
+    ADDR = 0x90
+    SUBR = 0x91
+    MULR = 0x92
+    DIVR = 0x93
+    MODR = 0x94
+    IMULR = 0x95
+    IDIVR = 0x96
+    IMODR = 0x97
+    CMPR = 0x99
+    ICMPR = 0x9A
+    ADCR = 0x9D
+    SBBR = 0x9E
+
+    MULW = 0xA0
+    IMULW = 0xA1
+
+    FADD = 0xB0
+    FSUB = 0xB1
+    FMUL = 0xB2
+    FDIV = 0xB3
+    FSQRT = 0xB4
+    FMOV = 0xB5
+    ITOF = 0xB6
+    FTOI = 0xB7
+    FCMP = 0xB8
+    FLD = 0xB9
+
+    MEMCPY = 0xC0
+    MEMSET = 0xC1
+    MEMCMP = 0xC2
+    DATACPY = 0xC3
+
+    NEWSTR = 0xD0
+    NEWARR = 0xD1
+    NEWREC = 0xD2
+    GETF = 0xD3
+    SETF = 0xD4
+    OLEN = 0xD5
+    OTYPE = 0xD6
+    GC = 0xD7
 }
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::opcode::OpCode;
+use crate::symbols::SymbolTable;
+use crate::Machine;
+
+/// Label used for addresses that come before every symbol
+const UNKNOWN: &str = "[unknown]";
+
+/// Counts how often every instruction is executed
+/// - PC counts: executions per program counter, with the opcode found there
+#[derive(Debug, Default)]
+pub struct Profiler {
+    pc_counts: HashMap<u16, (OpCode, u64)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one execution of `op` at `pc`
+    pub fn record(&mut self, pc: u16, op: OpCode) {
+        let entry = self.pc_counts.entry(pc).or_insert((op, 0));
+        entry.0 = op;
+        entry.1 += 1;
+    }
+
+    /// Total amount of executed instructions
+    pub fn total(&self) -> u64 {
+        self.pc_counts.values().map(|(_, c)| c).sum()
+    }
+
+    /// Executions per program counter, hottest first
+    pub fn by_pc(&self) -> Vec<(u16, OpCode, u64)> {
+        let mut counts: Vec<_> = self
+            .pc_counts
+            .iter()
+            .map(|(&pc, &(op, c))| (pc, op, c))
+            .collect();
+        counts.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        counts
+    }
+
+    /// Executions per opcode, hottest first
+    pub fn by_opcode(&self) -> Vec<(OpCode, u64)> {
+        let mut counts: HashMap<OpCode, u64> = HashMap::new();
+        for &(op, c) in self.pc_counts.values() {
+            *counts.entry(op).or_default() += c;
+        }
+        sorted(counts)
+    }
+
+    /// Executions per label, hottest first
+    pub fn by_label<'a>(&self, symbols: &'a SymbolTable) -> Vec<(&'a str, u64)> {
+        let mut counts: HashMap<&str, u64> = HashMap::new();
+        for (&pc, &(_, c)) in &self.pc_counts {
+            *counts.entry(label(symbols, pc)).or_default() += c;
+        }
+        sorted(counts)
+    }
+
+    /// Human readable hotspot report
+    pub fn report(&self, symbols: &SymbolTable) -> String {
+        let total = self.total().max(1);
+        let percent = |c: u64| c as f64 * 100.0 / total as f64;
+        let mut out = String::new();
+
+        let _ = writeln!(out, "| PROFILE: {} instructions", self.total());
+        let _ = writeln!(out, "|-----------------------------------------|");
+        let _ = writeln!(out, "| Hotspots by PC");
+        for (pc, op, c) in self.by_pc() {
+            let location = match symbols.lookup(pc) {
+                Some((name, offset)) => format!("{name}+{offset}"),
+                None => UNKNOWN.to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "| 0x{pc:04X} {location:<20} {:<8} {c:>10} {:>6.2}%",
+                format!("{op:?}"),
+                percent(c)
+            );
+        }
+        let _ = writeln!(out, "|-----------------------------------------|");
+        let _ = writeln!(out, "| Hotspots by label");
+        for (name, c) in self.by_label(symbols) {
+            let _ = writeln!(out, "| {name:<29} {c:>10} {:>6.2}%", percent(c));
+        }
+        let _ = writeln!(out, "|-----------------------------------------|");
+        let _ = writeln!(out, "| Hotspots by opcode");
+        for (op, c) in self.by_opcode() {
+            let _ = writeln!(
+                out,
+                "| {:<29} {c:>10} {:>6.2}%",
+                format!("{op:?}"),
+                percent(c)
+            );
+        }
+        out
+    }
+
+    /// Folded stacks (`label;OPCODE count` per line), as consumed by flamegraph tools
+    pub fn folded(&self, symbols: &SymbolTable) -> String {
+        let mut counts: HashMap<(&str, OpCode), u64> = HashMap::new();
+        for (&pc, &(op, c)) in &self.pc_counts {
+            *counts.entry((label(symbols, pc), op)).or_default() += c;
+        }
+        let mut lines: Vec<_> = counts
+            .into_iter()
+            .map(|((name, op), c)| format!("{name};{op:?} {c}"))
+            .collect();
+        lines.sort();
+        lines.iter().map(|l| format!("{l}\n")).collect()
+    }
+}
+
+fn label(symbols: &SymbolTable, pc: u16) -> &str {
+    symbols.lookup(pc).map_or(UNKNOWN, |(name, _)| name)
+}
+
+fn sorted<K: Ord>(counts: HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+impl Machine {
+    /// Starts counting executions per PC and per opcode
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    /// Collected profile, if profiling is enabled
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead};
+
+use crate::lines::{LineTable, SourceLine};
+use crate::symbols::SymbolTable;
+
+/// An assembled program, as stored in a `.proj` file
+/// - Memory : Instructions, loaded into the mutable memory
+/// - Data   : Loaded into the immutable data
+/// - Symbols: Label addresses, used for diagnostics and profiling
+/// - Lines  : Source line of every instruction, used for coverage
+/// - Version: Bytecode version the program was assembled for
+///
+/// `.proj` layout
+/// ```text
+/// [[VERSION 2]]           ; bytecode version, 1 when missing
+/// 0x00020x0004..          ; memory
+/// [[DATA]]
+/// 0x00540x0068..          ; data
+/// [[SYMBOLS]]
+/// main 0x0000             ; one `name address` pair per line
+/// [[LINES]]
+/// 0x0000 asm/main.asm:3   ; one `address file:line` pair per line
+/// ```
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub memory: Vec<u8>,
+    pub data: Vec<u8>,
+    pub symbols: SymbolTable,
+    pub lines: LineTable,
+    pub version: u8,
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self {
+            memory: Vec::new(),
+            data: Vec::new(),
+            symbols: SymbolTable::new(),
+            lines: LineTable::new(),
+            version: Self::VERSION,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Section {
+    Memory,
+    Data,
+    Symbols,
+    Lines,
+}
+
+impl Program {
+    /// Bytecode version written by the preprocessor
+    /// - 1: Registers `A`, `B`, `C`, `M`, `SP`, `PC`, `FLAGS` encoded as 0..=6
+    /// - 2: Registers `R0`..`R15` encoded as 0..=15, then `SP`, `PC`, `FLAGS`
+    pub const VERSION: u8 = 2;
+
+    /// Reads a `.proj` file
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let file = match File::open(file_path) {
+            Ok(f) => f,
+            Err(_) => return Err(format!("could not read {}", file_path)),
+        };
+
+        let reader = io::BufReader::new(file);
+        let lines = reader
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "could not read line".to_string())?;
+
+        Self::parse(&lines.join("\n"))
+    }
+
+    /// Parses the contents of a `.proj` file
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut program = Self {
+            version: 1,
+            ..Self::default()
+        };
+        let mut section = Section::Memory;
+
+        for line in source.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            match line {
+                "[[DATA]]" => {
+                    section = Section::Data;
+                    continue;
+                }
+                "[[SYMBOLS]]" => {
+                    section = Section::Symbols;
+                    continue;
+                }
+                "[[LINES]]" => {
+                    section = Section::Lines;
+                    continue;
+                }
+                _ => {}
+            }
+
+            if let Some(version) = line
+                .strip_prefix("[[VERSION ")
+                .and_then(|v| v.strip_suffix("]]"))
+            {
+                program.version = version
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid bytecode version `{}`", version))?;
+                if program.version > Self::VERSION {
+                    return Err(format!(
+                        "bytecode version {} is newer than supported version {}",
+                        program.version,
+                        Self::VERSION
+                    ));
+                }
+                continue;
+            }
+
+            if section == Section::Symbols {
+                let (name, address) = line
+                    .split_once(' ')
+                    .ok_or(format!("invalid symbol `{}`", line))?;
+                let address = parse_hex(address.trim())?;
+                program.symbols.insert(name, address);
+                continue;
+            }
+
+            if section == Section::Lines {
+                let (address, source) = line
+                    .split_once(' ')
+                    .ok_or(format!("invalid source line `{}`", line))?;
+                let address = parse_hex(address)?;
+                program
+                    .lines
+                    .insert(address, SourceLine::try_from(source.trim())?);
+                continue;
+            }
+
+            for chunk in line.split("0x") {
+                if chunk.is_empty() {
+                    continue;
+                }
+
+                let low = (parse_hex(chunk)? & 0xff) as u8;
+
+                if section == Section::Memory {
+                    program.memory.push(low);
+                } else {
+                    program.data.push(low);
+                }
+            }
+        }
+
+        Ok(program)
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    u16::from_str_radix(s, 16).map_err(|_| format!("could not parse hex number from `{}`", s))
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[[VERSION {}]]", self.version)?;
+        for x in &self.memory {
+            write!(f, "0x{x:04X?}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "[[DATA]]")?;
+        for x in &self.data {
+            write!(f, "0x{x:04X?}")?;
+        }
+        if !self.symbols.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "[[SYMBOLS]]")?;
+            for (address, name) in self.symbols.iter() {
+                writeln!(f, "{name} 0x{address:04X}")?;
+            }
+        }
+        if !self.lines.is_empty() {
+            if self.symbols.is_empty() {
+                writeln!(f)?;
+            }
+            writeln!(f, "[[LINES]]")?;
+            for (address, source) in self.lines.iter() {
+                writeln!(f, "0x{address:04X} {source}")?;
+            }
+        }
+        Ok(())
+    }
+}
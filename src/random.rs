@@ -0,0 +1,140 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::history::Write;
+use crate::{Machine, Register};
+
+/// Per-machine pseudo-random number generator, SplitMix64
+/// The same seed always gives the same numbers, it is not meant for cryptography
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rng {
+    pub state: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::from_time()
+    }
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeded from the host's clock, different on every run
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let v = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&v[..chunk.len()]);
+        }
+    }
+}
+
+/// Random syscalls, every operand is a register
+/// - `RANDOM dst`          : a random word into `dst`
+/// - `RANDOM_BYTES buf len`: `len` random bytes at `buf`
+impl Machine {
+    /// Reseeds the generator, the same seed gives the same run
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Advances the generator, recording its old state if history is enabled
+    fn random_bytes(&mut self, len: usize) -> Vec<u8> {
+        if let Some(history) = self.history.as_mut() {
+            history.record(Write::Random(self.rng));
+        }
+        let mut bytes = vec![0; len];
+        self.rng.fill(&mut bytes);
+        bytes
+    }
+
+    pub(crate) fn syscall_random(&mut self) -> Result<(), String> {
+        let dst: Register = self.fetch()?;
+        let bytes = self.random_bytes(2);
+        let v = u16::from_le_bytes([bytes[0], bytes[1]]);
+        self.write_register(dst, v);
+        if self.debug {
+            println!("| RANDOM: Reg {:?} -> 0x{:04X}", dst, v);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn syscall_random_bytes(&mut self) -> Result<(), String> {
+        let [buf, len] = self.fetch_values()?;
+        self.memory_range(buf as usize, len as usize)?;
+        let bytes = self.random_bytes(len as usize);
+        self.write_memory(buf as usize, &bytes)?;
+        if self.debug {
+            println!("| RANDOM_BYTES: 0x{:04X}, {} bytes", buf, len);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{machine, run};
+
+    fn seeded(source: &str, seed: u64) -> Machine {
+        let mut m = machine(source);
+        m.seed_random(seed);
+        m
+    }
+
+    #[test]
+    fn splitmix64() {
+        assert_eq!(Rng::new(0).next_u64(), 0xE220_A839_7B1D_CDAF);
+        let mut bytes = [0; 3];
+        Rng::new(0).fill(&mut bytes);
+        assert_eq!(bytes, [0xAF, 0xCD, 0x1D]);
+    }
+
+    #[test]
+    fn seeded_runs() {
+        let source = "SYSCALL RANDOM A\nADD B $0x10 $0\nADD C $5 $0\nMULR B B B\nSYSCALL RANDOM_BYTES B C\nHALT";
+        let mut m1 = seeded(source, 42);
+        let mut m2 = seeded(source, 42);
+        let mut m3 = seeded(source, 43);
+        for m in [&mut m1, &mut m2, &mut m3] {
+            run(m).unwrap();
+        }
+        assert_eq!(m1.register(Register::A), m2.register(Register::A));
+        assert_eq!(m1.memory[0x100..0x105], m2.memory[0x100..0x105]);
+        assert_ne!(m1.register(Register::A), m3.register(Register::A));
+
+        // A range out of bounds fails without drawing a number
+        let mut m = seeded("ADD C $2 $0\nSYSCALL RANDOM_BYTES B C\nHALT", 42);
+        m.registers[Register::B as usize] = 0xFFF;
+        assert!(run(&mut m).is_err());
+        assert_eq!(m.rng, Rng::new(42));
+    }
+
+    #[test]
+    fn undo() {
+        let mut m = seeded("SYSCALL RANDOM A\nHALT", 7);
+        m.enable_history();
+        m.step().unwrap();
+        let first = m.register(Register::A);
+        m.step_back().unwrap();
+        assert_eq!(m.rng, Rng::new(7));
+        m.step().unwrap();
+        assert_eq!(m.register(Register::A), first);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::flags::Flag;
+use crate::opcode::OpCode;
+use crate::{Machine, Register};
+
+/// Signed arithmetic, comparisons and byte extension
+///
+/// Registers hold two's complement values, immediates of signed instructions are sign-extended
+/// from a byte, so `IMUL A $-5 $3` puts `-15` (0xFFF1) into `A`
+///
+/// Operands
+/// - Immediate form: `OP reg v1 v2`, like `MUL`
+/// - Register form : `OPR dst src1 src2`
+/// - `NEG dst src`, `SEXT dst src`, `ZEXT dst src`
+/// - `CMP a v`, `CMPR a b`, `ICMP a v`, `ICMPR a b`
+///
+/// FLAGS
+/// - Arithmetic: like the unsigned group, O on signed overflow
+/// - Compares  : like `SUB a b`, except C is set when `a < b`, compared unsigned by `CMP`
+///   and signed by `ICMP`
+impl Machine {
+    /// MOD, IMUL, IDIV, IMOD and their register forms
+    pub(crate) fn handle_signed(&mut self, op: OpCode) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let register_form = matches!(
+            op,
+            OpCode::MODR | OpCode::IMULR | OpCode::IDIVR | OpCode::IMODR
+        );
+
+        let (result, carry, overflow) = if matches!(op, OpCode::MOD | OpCode::MODR) {
+            let a = self.fetch_operand(register_form)?;
+            let b = self.fetch_operand(register_form)?;
+            let result = a.checked_rem(b).ok_or("division by zero")?;
+            (result, false, false)
+        } else {
+            let a = self.fetch_signed_operand(register_form)?;
+            let b = self.fetch_signed_operand(register_form)?;
+            if b == 0 && !matches!(op, OpCode::IMUL | OpCode::IMULR) {
+                return Err("division by zero".to_string());
+            }
+            let (result, overflow) = match op {
+                OpCode::IMUL | OpCode::IMULR => a.overflowing_mul(b),
+                OpCode::IDIV | OpCode::IDIVR => a.overflowing_div(b),
+                OpCode::IMOD | OpCode::IMODR => a.overflowing_rem(b),
+                _ => unreachable!("{:?} is not a signed opcode", op),
+            };
+            (result as u16, overflow, overflow)
+        };
+
+        self.write_register(r, result);
+        self.set_flags(result, carry, overflow);
+        if self.debug {
+            println!("| {:?}: Reg {:?} -> 0x{:X}", op, r, result);
+        }
+        Ok(())
+    }
+
+    /// Put the two's complement negation of `src` into `dst`
+    /// C is set unless `src` is zero, O is set for -32768
+    pub(crate) fn handle_neg(&mut self) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let src: Register = self.fetch()?;
+        let a = self.register(src);
+        let (result, overflow) = (a as i16).overflowing_neg();
+        self.write_register(r, result as u16);
+        self.set_flags(result as u16, a != 0, overflow);
+        if self.debug {
+            println!("| NEG: Reg {:?} 0x{:X} -> 0x{:X}", r, a, result);
+        }
+        Ok(())
+    }
+
+    /// CMP, ICMP and their register forms
+    pub(crate) fn handle_cmp(&mut self, op: OpCode) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let a = self.register(r);
+        let (b, less) = match op {
+            OpCode::CMP | OpCode::CMPR => {
+                let b = self.fetch_operand(op == OpCode::CMPR)?;
+                (b, a < b)
+            }
+            OpCode::ICMP | OpCode::ICMPR => {
+                let b = self.fetch_signed_operand(op == OpCode::ICMPR)?;
+                (b as u16, (a as i16) < b)
+            }
+            _ => unreachable!("{:?} is not a compare opcode", op),
+        };
+        let result = a.wrapping_sub(b);
+        let overflow = (a as i16).overflowing_sub(b as i16).1;
+        self.set_flags(result, less, overflow);
+        if self.debug {
+            println!(
+                "| {:?}: Reg {:?} 0x{:X}, 0x{:X} less: {}",
+                op, r, a, b, less
+            );
+        }
+        Ok(())
+    }
+
+    /// SEXT and ZEXT, extend the low byte of `src` into a word in `dst`
+    pub(crate) fn handle_extend(&mut self, op: OpCode) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let src: Register = self.fetch()?;
+        let a = self.register(src);
+        let result = match op {
+            OpCode::SEXT => a as u8 as i8 as i16 as u16,
+            OpCode::ZEXT => a & 0xFF,
+            _ => unreachable!("{:?} is not an extend opcode", op),
+        };
+        self.write_register(r, result);
+        self.set_flag(Flag::Zero, result == 0);
+        self.set_flag(Flag::Sign, result & 0x8000 != 0);
+        if self.debug {
+            println!("| {:?}: Reg {:?} 0x{:X} -> 0x{:X}", op, r, a, result);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+use crate::gc::ObjectHeap;
+use crate::heap::Heap;
+use crate::history::History;
+use crate::random::Rng;
+use crate::{Machine, Register};
+
+/// Magic bytes every snapshot starts with
+pub const MAGIC: &[u8; 4] = b"NVMS";
+/// Current snapshot format version
+/// Version 1 only had the registers, memory, data and halt sections, 2 added the sections after them
+pub const VERSION: u16 = 2;
+
+/// Snapshot layout
+/// - Header : `MAGIC`, then `VERSION` as little endian u16
+/// - Body   : a list of sections, each one is `tag: u8`, `length: u32 (LE)`, `payload`
+///
+/// Unknown sections are skipped on restore, so newer devices can add their
+/// own sections without breaking older snapshots
+macro_rules! generate_sections {
+    ($($name:ident = $v:expr)*) => {
+        #[derive(Debug, PartialEq, Copy, Clone)]
+        #[repr(u8)]
+        pub enum Section {
+            $(
+                $name = $v,
+            )*
+        }
+        impl TryFrom<u8> for Section {
+            type Error = String;
+            fn try_from(value: u8) -> Result<Self, Self::Error> {
+                match value {
+                    $(
+                        $v => Ok(Self::$name),
+                    )*
+                    _ => Err(format!("{} is not a valid snapshot section", value))
+                }
+            }
+        }
+    };
+}
+
+generate_sections! {
+    REGISTERS = 1
+    MEMORY = 2
+    DATA = 3
+    HALT = 4
+    INSTRUCTIONS = 5
+    VERSION = 6
+    FPU = 7
+    HEAP = 8
+    OBJECTS = 9
+    RANDOM = 10
+    CLOCK = 11
+}
+
+/// Builds a snapshot one section at a time
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        Self { bytes }
+    }
+
+    /// Appends a section with the given payload
+    pub fn section(&mut self, section: Section, payload: &[u8]) {
+        self.bytes.push(section as u8);
+        self.bytes
+            .extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.bytes.extend_from_slice(payload);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Walks the sections of a snapshot
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the header and returns a reader positioned at the first section
+    pub fn new(bytes: &'a [u8]) -> Result<Self, String> {
+        let header_len = MAGIC.len() + 2;
+        if bytes.len() < header_len || &bytes[..MAGIC.len()] != MAGIC {
+            return Err("not a NovaVM snapshot".to_string());
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version > VERSION {
+            return Err(format!(
+                "snapshot version {} is newer than supported version {}",
+                version, VERSION
+            ));
+        }
+        Ok(Self {
+            bytes: &bytes[header_len..],
+        })
+    }
+
+    /// Returns the next section, `None` once the snapshot is exhausted
+    /// Sections with an unknown tag are skipped
+    pub fn next_section(&mut self) -> Result<Option<(Section, &'a [u8])>, String> {
+        loop {
+            if self.bytes.is_empty() {
+                return Ok(None);
+            }
+            if self.bytes.len() < 5 {
+                return Err("truncated snapshot section header".to_string());
+            }
+            let tag = self.bytes[0];
+            let len =
+                u32::from_le_bytes([self.bytes[1], self.bytes[2], self.bytes[3], self.bytes[4]])
+                    as usize;
+            let rest = &self.bytes[5..];
+            if rest.len() < len {
+                return Err(format!("truncated snapshot section {}", tag));
+            }
+            let (payload, rest) = rest.split_at(len);
+            self.bytes = rest;
+            if let Ok(section) = Section::try_from(tag) {
+                return Ok(Some((section, payload)));
+            }
+        }
+    }
+}
+
+impl Machine {
+    /// Serialises the full machine state into the versioned snapshot format
+    /// Fails while the guest has files open, they could not be restored
+    pub fn snapshot(&self) -> Result<Vec<u8>, String> {
+        let open = self.fs.open_files();
+        if open > 0 {
+            return Err(format!("cannot snapshot with {} files open", open));
+        }
+        let mut w = Writer::new();
+
+        let mut registers = vec![self.registers.len() as u8];
+        for r in self.registers {
+            registers.extend_from_slice(&r.to_le_bytes());
+        }
+        w.section(Section::REGISTERS, &registers);
+        w.section(Section::MEMORY, &self.memory);
+        w.section(Section::DATA, &self.data);
+        w.section(Section::HALT, &[self.halt as u8]);
+        w.section(Section::INSTRUCTIONS, &self.instruction_count.to_le_bytes());
+        w.section(Section::VERSION, &[self.version]);
+
+        let mut fpu = vec![self.fpu as u8];
+        for f in self.fregisters {
+            fpu.extend_from_slice(&f.to_le_bytes());
+        }
+        w.section(Section::FPU, &fpu);
+        w.section(Section::HEAP, &self.heap.to_bytes());
+        w.section(Section::OBJECTS, &self.objects.to_bytes());
+        w.section(Section::RANDOM, &self.rng.state.to_le_bytes());
+        if let Some(clock) = self.clock.save() {
+            w.section(Section::CLOCK, &clock);
+        }
+
+        Ok(w.finish())
+    }
+
+    /// Restores the machine state from a snapshot made by [`Machine::snapshot`]
+    /// The machine is left untouched if the snapshot is invalid
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut registers = [0; Self::REGISTER_COUNT];
+        let mut memory = [0; Self::MEMORY_LENGTH];
+        let mut data = [0; Self::DATA_LENGTH];
+        let mut halt = false;
+        let mut instruction_count = 0;
+        // Snapshots from before the bytecode version section only ran version 1 programs
+        let mut version = 1;
+        let mut fpu = self.fpu;
+        let mut fregisters = [0.0; Self::FREGISTER_COUNT];
+        let mut heap = Heap::new();
+        let mut objects = ObjectHeap::new();
+        let mut rng = self.rng;
+        let mut clock = None;
+        // Without these the old state would silently stay in place
+        let mut missing = vec![Section::REGISTERS, Section::MEMORY, Section::DATA];
+
+        let mut r = Reader::new(bytes)?;
+        while let Some((section, payload)) = r.next_section()? {
+            missing.retain(|&s| s != section);
+            match section {
+                Section::REGISTERS => {
+                    let count = *payload.first().ok_or("empty register section")? as usize;
+                    if payload.len() != 1 + count * 2 {
+                        return Err("invalid register section".to_string());
+                    }
+                    let values: Vec<u16> = payload[1..]
+                        .chunks_exact(2)
+                        .map(|v| u16::from_le_bytes([v[0], v[1]]))
+                        .collect();
+                    match count {
+                        Self::REGISTER_COUNT => registers.copy_from_slice(&values),
+                        // Before R4..R15: A, B, C, M, SP, PC, FLAGS
+                        7 => {
+                            registers[..4].copy_from_slice(&values[..4]);
+                            registers[Register::SP as usize] = values[4];
+                            registers[Register::PC as usize] = values[5];
+                            registers[Register::FLAGS as usize] = values[6];
+                        }
+                        _ => {
+                            return Err(format!(
+                                "snapshot has {} registers, machine has {}",
+                                count,
+                                Self::REGISTER_COUNT
+                            ))
+                        }
+                    }
+                }
+                Section::MEMORY => {
+                    if payload.len() != Self::MEMORY_LENGTH {
+                        return Err(format!(
+                            "snapshot memory is {} bytes, machine has {}",
+                            payload.len(),
+                            Self::MEMORY_LENGTH
+                        ));
+                    }
+                    memory.copy_from_slice(payload);
+                }
+                Section::DATA => {
+                    if payload.len() != Self::DATA_LENGTH {
+                        return Err(format!(
+                            "snapshot data is {} bytes, machine has {}",
+                            payload.len(),
+                            Self::DATA_LENGTH
+                        ));
+                    }
+                    data.copy_from_slice(payload);
+                }
+                Section::HALT => {
+                    halt = payload.first().is_some_and(|&h| h != 0);
+                }
+                Section::INSTRUCTIONS => {
+                    let count: [u8; 8] = payload
+                        .try_into()
+                        .map_err(|_| "invalid instruction count section".to_string())?;
+                    instruction_count = u64::from_le_bytes(count);
+                }
+                Section::VERSION => {
+                    version = *payload.first().ok_or("empty bytecode version section")?;
+                }
+                Section::HEAP => heap = Heap::from_bytes(payload)?,
+                Section::OBJECTS => objects = ObjectHeap::from_bytes(payload)?,
+                Section::RANDOM => {
+                    let state: [u8; 8] = payload
+                        .try_into()
+                        .map_err(|_| "invalid random section".to_string())?;
+                    rng = Rng::new(u64::from_le_bytes(state));
+                }
+                Section::CLOCK => clock = Some(payload),
+                Section::FPU => {
+                    if payload.len() != 1 + Self::FREGISTER_COUNT * 8 {
+                        return Err("invalid floating-point section".to_string());
+                    }
+                    fpu = payload[0] != 0;
+                    for (i, v) in payload[1..].chunks_exact(8).enumerate() {
+                        fregisters[i] = f64::from_le_bytes(v.try_into().unwrap());
+                    }
+                }
+            }
+        }
+
+        if let Some(section) = missing.first() {
+            return Err(format!("snapshot has no {:?} section", section));
+        }
+        // Can still fail, so before anything is replaced
+        if let Some(clock) = clock {
+            self.clock.load(clock)?;
+        }
+
+        self.registers = registers;
+        self.memory = memory;
+        self.data = data;
+        self.halt = halt;
+        self.instruction_count = instruction_count;
+        self.version = version;
+        self.fpu = fpu;
+        self.fregisters = fregisters;
+        self.heap = heap;
+        self.objects = objects;
+        self.rng = rng;
+        if let Some(history) = self.history.as_mut() {
+            *history = History::new();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    #[test]
+    fn virtual_clock() {
+        let mut m = Machine::new();
+        m.set_clock(Box::new(VirtualClock::new()));
+        m.clock.sleep(1500);
+        m.instruction_count = 2000;
+        let snapshot = m.snapshot().unwrap();
+
+        let mut restored = Machine::new();
+        restored.set_clock(Box::new(VirtualClock::new()));
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.clock.monotonic(restored.instruction_count), 1502);
+
+        // A truncated clock section leaves the machine untouched
+        // Tag, length and 24 bytes of state come last
+        let mut bad = snapshot.clone();
+        let at = bad.len() - 28;
+        bad.pop();
+        bad[at..at + 4].copy_from_slice(&23u32.to_le_bytes());
+        let mut m = Machine::new();
+        m.set_clock(Box::new(VirtualClock::new()));
+        assert_eq!(m.restore(&bad).unwrap_err(), "invalid clock section");
+        assert_eq!(m.instruction_count, 0);
+    }
+
+    #[test]
+    fn required_sections() {
+        let mut m = Machine::new();
+        m.registers[Register::A as usize] = 7;
+        for section in [Section::REGISTERS, Section::MEMORY, Section::DATA] {
+            let snapshot = Machine::new().snapshot().unwrap();
+            let mut r = Reader::new(&snapshot).unwrap();
+            let mut w = Writer::new();
+            while let Some((s, payload)) = r.next_section().unwrap() {
+                if s != section {
+                    w.section(s, payload);
+                }
+            }
+            assert_eq!(
+                m.restore(&w.finish()).unwrap_err(),
+                format!("snapshot has no {:?} section", section)
+            );
+            assert_eq!(m.register(Register::A), 7);
+        }
+    }
+
+    #[test]
+    fn newer_version() {
+        let mut snapshot = Machine::new().snapshot().unwrap();
+        snapshot[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Machine::new().restore(&snapshot).unwrap_err(),
+            format!(
+                "snapshot version {} is newer than supported version {}",
+                VERSION + 1,
+                VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn open_files() {
+        let root = std::env::temp_dir().join(format!("novavm-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut m = Machine::new();
+        m.set_fs_root(&root).unwrap();
+        let fd = m.fs.open(".", 0).unwrap();
+        assert_eq!(
+            m.snapshot().unwrap_err(),
+            "cannot snapshot with 1 files open"
+        );
+        m.fs.close(fd).unwrap();
+        assert!(m.snapshot().is_ok());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
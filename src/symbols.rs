@@ -0,0 +1,45 @@
+/// Maps label names to addresses in memory
+/// Symbols are kept sorted by address so an address can be attributed to the label it falls under
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    symbols: Vec<(u16, String)>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a symbol, replacing any previous symbol with the same name
+    pub fn insert(&mut self, name: &str, address: u16) {
+        self.symbols.retain(|(_, n)| n != name);
+        let at = self.symbols.partition_point(|(a, _)| *a <= address);
+        self.symbols.insert(at, (address, name.to_string()));
+    }
+
+    /// Address of the symbol `name`
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.symbols
+            .iter()
+            .find(|(_, n)| n == name)
+            .map(|(a, _)| *a)
+    }
+
+    /// Closest symbol at or before `address`, with the offset of `address` from it
+    pub fn lookup(&self, address: u16) -> Option<(&str, u16)> {
+        let at = self.symbols.partition_point(|(a, _)| *a <= address);
+        at.checked_sub(1).map(|i| {
+            let (a, n) = &self.symbols[i];
+            (n.as_str(), address - a)
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Iterates over `(address, name)` in address order
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.symbols.iter().map(|(a, n)| (*a, n.as_str()))
+    }
+}
@@ -2,6 +2,8 @@ use crate::Machine;
 
 macro_rules! generate_syscalls {
     ($($name:ident = $v:expr)*) => {
+        // Syscall names are written as is in assembly, e.g. `RANDOM_BYTES`
+        #[allow(non_camel_case_types)]
         #[derive(Debug, PartialEq, Copy, Clone)]
         #[repr(u8)]
         pub enum Syscall {
@@ -47,6 +49,28 @@ generate_syscalls! {
     EXIT = 1
     READ = 3
     WRITE = 4
+    OPEN = 5
+    CLOSE = 6
+    UNLINK = 10
+    TIME = 13
+    SEEK = 19
+    RANDOM = 40
+    RANDOM_BYTES = 41
+    ARGS = 64
+    ARG = 65
+    ENV = 66
+    MONOTONIC = 78
+    ALLOC = 90
+    FREE = 91
+    STAT = 106
+    READDIR = 141
+    SLEEP = 162
+    REALLOC = 163
+    PRINT_INT = 200
+    PRINT_CHAR = 201
+    PRINT_STR = 202
+    READ_LINE = 203
+    READ_INT = 204
 }
 
 impl Syscall {
@@ -58,13 +82,19 @@ impl Syscall {
                     print!("| WRITE ");
                 }
                 let mode: u8 = m.fetch()?;
+                if mode == 0 {
+                    if m.debug {
+                        println!("FD");
+                    }
+                    return m.syscall_write_fd();
+                }
                 let start: usize = m.fetch()?;
                 let end: usize = m.fetch()?;
                 if mode == 1 {
                     if m.debug {
                         println!("STDOUT");
                     }
-                    let data = &m.data[start..start + end as usize];
+                    let data = &m.data[start..start + end];
                     /* if let Some(&0) = data.last() {
                         let output = std::str::from_utf8(&data[..data.len() - 1]).map_err(|e| e.to_string())?;
                         print!("{}", output);
@@ -83,7 +113,29 @@ impl Syscall {
                     return Err(format!("Unsupported mode: {}", mode));
                 }
             }
-            _ => return Err(format!("{:?} syscall not implemented", self)),
+            Self::OPEN => m.syscall_open()?,
+            Self::CLOSE => m.syscall_close()?,
+            Self::READ => m.syscall_read()?,
+            Self::SEEK => m.syscall_seek()?,
+            Self::STAT => m.syscall_stat()?,
+            Self::UNLINK => m.syscall_unlink()?,
+            Self::READDIR => m.syscall_readdir()?,
+            Self::ARGS => m.syscall_args()?,
+            Self::ARG => m.syscall_arg()?,
+            Self::ENV => m.syscall_env()?,
+            Self::TIME => m.syscall_time()?,
+            Self::MONOTONIC => m.syscall_monotonic()?,
+            Self::SLEEP => m.syscall_sleep()?,
+            Self::RANDOM => m.syscall_random()?,
+            Self::RANDOM_BYTES => m.syscall_random_bytes()?,
+            Self::PRINT_INT => m.syscall_print_int()?,
+            Self::PRINT_CHAR => m.syscall_print_char()?,
+            Self::PRINT_STR => m.syscall_print_str()?,
+            Self::READ_LINE => m.syscall_read_line()?,
+            Self::READ_INT => m.syscall_read_int()?,
+            Self::ALLOC => m.syscall_alloc()?,
+            Self::REALLOC => m.syscall_realloc()?,
+            Self::FREE => m.syscall_free()?,
         }
         Ok(())
     }
@@ -0,0 +1,147 @@
+use crate::flags::Flag;
+use crate::opcode::OpCode;
+use crate::{Machine, Register};
+
+/// Instructions for arithmetic wider than a register
+///
+/// A 32-bit value lives in a register pair, high word first, e.g. `A:B`
+/// Carry chains add the low words first, then the high words with the carry:
+/// ```asm
+/// ; A:B += C:M
+/// ADDR B B M
+/// ADCR A A C
+/// ```
+///
+/// Operands
+/// - `ADC reg v1 v2`, `SBB reg v1 v2`
+/// - `ADCR dst src1 src2`, `SBBR dst src1 src2`
+/// - `MULW src1 src2`, `IMULW src1 src2`, the 32-bit product goes into `A:B`
+impl Machine {
+    /// ADC, SBB and their register forms, add or subtract with the carry flag
+    /// Sets FLAGS like `ADD` and `SUB`, C and O account for the carry going in
+    pub(crate) fn handle_carry(&mut self, op: OpCode) -> Result<(), String> {
+        let r: Register = self.fetch()?;
+        let register_form = matches!(op, OpCode::ADCR | OpCode::SBBR);
+        let a = self.fetch_operand(register_form)?;
+        let b = self.fetch_operand(register_form)?;
+        let c = self.flag(Flag::Carry) as i32;
+
+        let (wide, signed) = match op {
+            OpCode::ADC | OpCode::ADCR => (
+                a as i32 + b as i32 + c,
+                a as i16 as i32 + b as i16 as i32 + c,
+            ),
+            OpCode::SBB | OpCode::SBBR => (
+                a as i32 - b as i32 - c,
+                a as i16 as i32 - b as i16 as i32 - c,
+            ),
+            _ => unreachable!("{:?} is not a carry opcode", op),
+        };
+        let result = wide as u16;
+        let carry = !(0..=u16::MAX as i32).contains(&wide);
+        let overflow = !(i16::MIN as i32..=i16::MAX as i32).contains(&signed);
+
+        self.write_register(r, result);
+        self.set_flags(result, carry, overflow);
+        if self.debug {
+            println!(
+                "| {:?}: Reg {:?} 0x{:X}, 0x{:X}, carry {} -> 0x{:X}",
+                op, r, a, b, c, result
+            );
+        }
+        Ok(())
+    }
+
+    /// MULW and IMULW, multiply two registers into the `A:B` register pair
+    /// Sets FLAGS: Z and S from the 32-bit result, C and O when the high word is needed
+    pub(crate) fn handle_mulw(&mut self, op: OpCode) -> Result<(), String> {
+        let r1: Register = self.fetch()?;
+        let r2: Register = self.fetch()?;
+        let a = self.register(r1);
+        let b = self.register(r2);
+
+        let (result, fits) = match op {
+            OpCode::MULW => {
+                let result = a as u32 * b as u32;
+                (result, result <= u16::MAX as u32)
+            }
+            OpCode::IMULW => {
+                let result = a as i16 as i32 * b as i16 as i32;
+                (result as u32, i16::try_from(result).is_ok())
+            }
+            _ => unreachable!("{:?} is not a wide multiply opcode", op),
+        };
+        let high = (result >> 16) as u16;
+        let low = result as u16;
+
+        self.write_register(Register::A, high);
+        self.write_register(Register::B, low);
+        self.set_flags(high, !fits, !fits);
+        self.set_flag(Flag::Zero, result == 0);
+        if self.debug {
+            println!(
+                "| {:?}: Reg {:?} 0x{:X}, Reg {:?} 0x{:X} -> 0x{:04X}:{:04X}",
+                op, r1, a, r2, b, high, low
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{machine, run};
+
+    /// Runs `source` with `A`, `B`, `C` and `M` set, returns `A:B`
+    fn pair(source: &str, [a, b, c, d]: [u16; 4]) -> (Machine, u32) {
+        let mut m = machine(source);
+        for (r, v) in [Register::A, Register::B, Register::C, Register::M]
+            .into_iter()
+            .zip([a, b, c, d])
+        {
+            m.registers[r as usize] = v;
+        }
+        run(&mut m).unwrap();
+        let ab = (m.register(Register::A) as u32) << 16 | m.register(Register::B) as u32;
+        (m, ab)
+    }
+
+    #[test]
+    fn carry_chains() {
+        let add = "ADDR B B M\nADCR A A C\nHALT";
+        let (m, ab) = pair(add, [0x0001, 0xFFFF, 0x0000, 0x0001]);
+        assert_eq!(ab, 0x0002_0000);
+        assert!(!m.flag(Flag::Carry));
+        let (m, ab) = pair(add, [0xFFFF, 0xFFFF, 0x0000, 0x0001]);
+        assert_eq!(ab, 0);
+        assert!(m.flag(Flag::Carry));
+
+        let sub = "SUBR B B M\nSBBR A A C\nHALT";
+        let (m, ab) = pair(sub, [0x0002, 0x0000, 0x0000, 0x0001]);
+        assert_eq!(ab, 0x0001_FFFF);
+        assert!(!m.flag(Flag::Carry));
+        let (m, ab) = pair(sub, [0x0000, 0x0000, 0x0000, 0x0001]);
+        assert_eq!(ab, 0xFFFF_FFFF);
+        assert!(m.flag(Flag::Carry));
+    }
+
+    #[test]
+    fn wide_multiply() {
+        let (m, ab) = pair("MULW C M\nHALT", [0, 0, 0xFFFF, 0xFFFF]);
+        assert_eq!(ab, 0xFFFE_0001);
+        assert!(m.flag(Flag::Carry) && m.flag(Flag::Overflow));
+        let (m, ab) = pair("MULW C M\nHALT", [0, 0, 0x00FF, 0x0101]);
+        assert_eq!(ab, 0xFFFF);
+        assert!(!m.flag(Flag::Carry));
+
+        let (m, ab) = pair("IMULW C M\nHALT", [0, 0, -2i16 as u16, 3]);
+        assert_eq!(ab, -6i32 as u32);
+        assert!(!m.flag(Flag::Carry) && m.flag(Flag::Sign));
+        let (m, ab) = pair("IMULW C M\nHALT", [0, 0, 0x4000, 4]);
+        assert_eq!(ab, 0x0001_0000);
+        assert!(m.flag(Flag::Overflow));
+        let (m, _) = pair("IMULW C M\nHALT", [0, 0, 0, 7]);
+        assert!(m.flag(Flag::Zero));
+    }
+}